@@ -0,0 +1,106 @@
+//! Controls a running FlightWand over its control socket.
+
+use flightwand::{default_socket_path, pipe::VRInputBounds};
+use serde_json::{Value, json};
+use std::{
+    io::{BufRead, BufReader, Write},
+    os::unix::net::UnixStream,
+    path::PathBuf,
+    process::ExitCode,
+};
+
+const USAGE: &str = "Usage: flightwand-ctl [--socket <path>] <command>
+
+Commands:
+    start [left|right]         Start OpenXR with the given hand (default: right)
+    recenter                   Use the controller's current orientation as neutral
+    bounds <deadzone> <max>    Set the stick deadzone and one full deflection for every direction
+    up <x,y,z>                 Set the controller direction that counts as neutral
+    reset-trim                 Return the trim to center
+    record <start|stop>        Start or stop recording a flight
+    query                      Print the current state
+    subscribe <topics>         Print telemetry until interrupted, topics are `rotation`, `output` and `gamepad`, comma separated
+    send <json>                Send a raw command";
+
+/// Comma separated numbers, or `None` if any of them isn't one.
+fn numbers(value: &str) -> Option<Vec<f32>> { value.split(',').map(|v| v.trim().parse().ok()).collect() }
+
+/// Turns the command line into a protocol command.
+fn command(args: &[String]) -> Result<Value, String> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        ["start"] => Ok(json!({ "cmd": "start" })),
+        ["start", hand] => Ok(json!({ "cmd": "start", "hand": hand })),
+        ["recenter"] => Ok(json!({ "cmd": "recenter" })),
+        ["bounds", deadzone, max] => {
+            let deadzone: f32 = deadzone.parse().map_err(|_| format!("`{deadzone}` isn't a number"))?;
+            let max: f32 = max.parse().map_err(|_| format!("`{max}` isn't a number"))?;
+            VRInputBounds { deadzone, stick_max: max, ..Default::default() }.check()?;
+            Ok(json!({ "cmd": "update_bounds", "deadzone": deadzone, "max": max }))
+        }
+        ["up", up] => match numbers(up).as_deref() {
+            Some([x, y, z]) => Ok(json!({ "cmd": "update_identity", "up": [x, y, z] })),
+            _ => Err(format!("`{up}` needs three numbers like `0,0.2,-1`")),
+        },
+        ["reset-trim"] => Ok(json!({ "cmd": "reset_trim" })),
+        ["record", "start"] => Ok(json!({ "cmd": "start_recording" })),
+        ["record", "stop"] => Ok(json!({ "cmd": "stop_recording" })),
+        ["query"] => Ok(json!({ "cmd": "query" })),
+        ["subscribe", topics] => Ok(json!({ "cmd": "subscribe", "topics": topics.split(',').collect::<Vec<_>>() })),
+        ["send", raw] => serde_json::from_str(raw).map_err(|err| format!("invalid JSON: {err}")),
+        _ => Err(USAGE.to_owned()),
+    }
+}
+
+fn run(mut args: Vec<String>) -> Result<(), String> {
+    let mut socket = default_socket_path();
+    if args.first().is_some_and(|arg| arg == "--socket") {
+        if args.len() < 2 {
+            return Err("--socket needs a path".to_owned());
+        }
+        socket = PathBuf::from(args.remove(1));
+        args.remove(0);
+    }
+
+    let command = command(&args)?;
+    let subscribe = command.get("cmd").and_then(Value::as_str) == Some("subscribe");
+
+    let mut stream = UnixStream::connect(&socket)
+        .map_err(|err| format!("Couldn't connect to FlightWand at {}: {err}", socket.display()))?;
+    stream.write_all(format!("{command}\n").as_bytes()).map_err(|err| err.to_string())?;
+
+    let mut lines = BufReader::new(stream).lines();
+    let reply = lines.next().ok_or("FlightWand closed the connection")?.map_err(|err| err.to_string())?;
+    let parsed: Value = serde_json::from_str(&reply).map_err(|err| format!("invalid reply: {err}"))?;
+    if parsed.get("ok").and_then(Value::as_bool) != Some(true) {
+        return Err(parsed.get("error").and_then(Value::as_str).unwrap_or(&reply).to_owned());
+    }
+    match parsed.get("state") {
+        Some(state) => println!("{}", serde_json::to_string_pretty(state).unwrap()),
+        None if !subscribe => println!("ok"),
+        None => {}
+    }
+
+    if subscribe {
+        for line in lines {
+            println!("{}", line.map_err(|err| err.to_string())?);
+        }
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.is_empty() || args.iter().any(|arg| arg == "--help") {
+        println!("{USAGE}");
+        return if args.is_empty() { ExitCode::FAILURE } else { ExitCode::SUCCESS };
+    }
+
+    match run(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{err}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,321 @@
+use crate::{
+    pipe::{Hand, UI2VR, VR2UI, VRInputBounds},
+    recording,
+    util::{self, runtime::Runtime},
+};
+use log::LevelFilter;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc,
+    },
+    time::Duration,
+};
+
+const USAGE: &str = "Usage: FlightWand [--headless] [OPTIONS]
+
+Without --headless, opens the settings window, starting from the options given.
+
+Options:
+    --headless             Run without a window, printing status to the terminal
+    --hand <left|right>    Controller to use as the stick (default: right)
+    --deadzone <0..1>      Stick deadzone
+    --max <0..1>           Deflection that maps to full stick output
+    --smoothing <seconds>  How much to smooth the stick before the deadzone (default: 0)
+    --up <x,y,z>           Controller direction that counts as neutral
+    --runtime <manifest>   OpenXR runtime manifest to load instead of the remembered or active one
+    --profile <file>       Read options from a file, one `name value` per line, or just `headless`. Later flags override it
+    --log-level <level>    One of error, warn, info, debug, trace
+    --export-csv <file>    Convert a recording to CSV next to it and exit
+    --socket <path>        Where to listen for flightwand-ctl (default: $XDG_RUNTIME_DIR/flightwand.sock)
+    --help                 Print this message";
+
+/// Everything that can be set from the command line or a profile.
+pub struct Options {
+    pub headless: bool,
+    pub hand: Hand,
+    pub bounds: VRInputBounds,
+    pub up: [f32; 3],
+    pub runtime: Option<PathBuf>,
+    pub log_level: LevelFilter,
+    pub socket: PathBuf,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            headless: false,
+            hand: Hand::Right,
+            bounds: VRInputBounds::default(),
+            up: [-0.02, 0.2, -1.0],
+            runtime: None,
+            log_level: LevelFilter::Info,
+            socket: flightwand::default_socket_path(),
+        }
+    }
+}
+
+fn parse_unit(name: &str, value: &str) -> Result<f32, String> {
+    match value.parse::<f32>() {
+        Ok(value) if (0.0..=1.0).contains(&value) => Ok(value),
+        _ => Err(format!("--{name} needs a number between 0 and 1, got `{value}`")),
+    }
+}
+
+impl Options {
+    /// Parses the process arguments, exiting after printing the usage for `--help`.
+    pub fn from_args() -> Result<Options, String> { Options::parse(std::env::args().skip(1)) }
+
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+        let mut options = Options::default();
+        while let Some(arg) = args.next() {
+            let Some(name) = arg.strip_prefix("--") else {
+                return Err(format!("Unexpected argument `{arg}`\n\n{USAGE}"));
+            };
+            match name {
+                "help" => {
+                    println!("{USAGE}");
+                    std::process::exit(0);
+                }
+                "headless" => options.headless = true,
+                // only here, so a profile can't turn the daemon into a one-off conversion
+                "export-csv" => {
+                    let value = args.next().ok_or("--export-csv needs a value")?;
+                    match recording::export_csv(Path::new(&value)) {
+                        Ok(csv) => println!("Exported to {}", csv.display()),
+                        Err(err) => {
+                            eprintln!("Couldn't export {}: {}", value, err);
+                            std::process::exit(1);
+                        }
+                    }
+                    std::process::exit(0);
+                }
+                _ => {
+                    let value = args.next().ok_or_else(|| format!("--{name} needs a value"))?;
+                    options.set(name, &value)?;
+                }
+            }
+        }
+        Ok(options)
+    }
+
+    fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        match name {
+            "hand" => {
+                self.hand = match value.to_ascii_lowercase().as_str() {
+                    "left" => Hand::Left,
+                    "right" => Hand::Right,
+                    _ => return Err(format!("--hand needs `left` or `right`, got `{value}`")),
+                }
+            }
+            "deadzone" => self.bounds.deadzone = parse_unit(name, value)?,
+            "max" => self.bounds.stick_max = parse_unit(name, value)?,
+            "smoothing" => {
+                self.bounds.smoothing = value
+                    .parse()
+                    .ok()
+                    .filter(|seconds: &f32| *seconds >= 0.0)
+                    .ok_or(format!("--smoothing needs a number of seconds, got `{value}`"))?;
+            }
+            "up" => {
+                let up: Result<Vec<f32>, _> = value.split(',').map(|v| v.trim().parse()).collect();
+                self.up = up
+                    .ok()
+                    .and_then(|up| up.try_into().ok())
+                    .ok_or_else(|| format!("--up needs three numbers like `0,0.2,-1`, got `{value}`"))?;
+            }
+            "runtime" => self.runtime = Some(PathBuf::from(value)),
+            "socket" => self.socket = PathBuf::from(value),
+            "profile" => self.load_profile(value)?,
+            "log-level" => {
+                self.log_level = value.parse().map_err(|_| format!("--log-level doesn't know `{value}`"))?;
+            }
+            _ => return Err(format!("Unknown option `--{name}`\n\n{USAGE}")),
+        }
+        Ok(())
+    }
+
+    /// The runtime given with `--runtime`, or else the one picked last time.
+    pub fn pick_runtime(&self) -> Option<Runtime> {
+        // an explicit manifest doesn't have to be in a standard location
+        let manifest = self.runtime.clone().or_else(util::runtime::remembered)?;
+        let runtime = manifest.canonicalize().ok().and_then(|manifest| Runtime::from_manifest(&manifest));
+        if runtime.is_none() {
+            log::warn!("Couldn't read runtime manifest {}, using the active runtime", manifest.display());
+        }
+        runtime
+    }
+
+    fn load_profile(&mut self, path: &str) -> Result<(), String> {
+        let profile = fs::read_to_string(path).map_err(|err| format!("Couldn't read profile `{path}`: {err}"))?;
+        for line in profile.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (name, value) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            if name == "profile" {
+                return Err(format!("Profile `{path}` can't include another profile"));
+            }
+            if name == "export-csv" {
+                return Err(format!("{path}: `{name}` can only be given on the command line"));
+            }
+            // the one flag, on a line of its own
+            if name == "headless" {
+                if !value.trim().is_empty() {
+                    return Err(format!("{path}: `headless` doesn't take a value"));
+                }
+                self.headless = true;
+                continue;
+            }
+            self.set(name, value.trim()).map_err(|err| format!("{path}: {err}"))?;
+        }
+        Ok(())
+    }
+}
+
+static STOP: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn request_stop(_: libc::c_int) { STOP.store(true, Ordering::Relaxed); }
+
+/// Runs the backend without a window until it fails or the process is told to stop.
+pub fn run_headless(options: Options, tx: mpsc::Sender<UI2VR>, rx: mpsc::Receiver<VR2UI>) -> ExitCode {
+    unsafe {
+        libc::signal(libc::SIGINT, request_stop as *const () as libc::sighandler_t);
+        libc::signal(libc::SIGTERM, request_stop as *const () as libc::sighandler_t);
+    }
+
+    let _ = tx.send(UI2VR::SelectRuntime(options.pick_runtime()));
+    let _ = tx.send(UI2VR::Start(options.hand));
+    // settings sent before starting are dropped, these wait in the queue until the backend is up
+    let _ = tx.send(UI2VR::UpdateBounds(options.bounds));
+    let _ = tx.send(UI2VR::UpdateIdentity(options.up));
+    log::info!("Starting with the {} hand", options.hand);
+
+    loop {
+        if STOP.load(Ordering::Relaxed) {
+            log::info!("Shutting down");
+            let _ = tx.send(UI2VR::Shutdown);
+            // the backend drops its sender once it has cleaned up
+            while let Ok(_msg) = rx.recv_timeout(Duration::from_secs(5)) {}
+            return ExitCode::SUCCESS;
+        }
+
+        match rx.recv_timeout(Duration::from_millis(100)) {
+            Ok(VR2UI::Running(info)) => log::info!("Running on {}", info.system_properties.system_name),
+            Ok(VR2UI::Failure(failure)) => {
+                log::error!("{}", failure);
+                return ExitCode::FAILURE;
+            }
+            Ok(VR2UI::RecordingUpdate(Some(path))) => log::info!("Recording to {}", path.display()),
+            Ok(VR2UI::RecordingUpdate(None)) => log::info!("Recording stopped"),
+            Ok(VR2UI::IdentityUpdate(up)) => log::info!("Recentered, up is {:?}", up),
+            Ok(VR2UI::GamepadDevice(path)) => log::info!("Virtual gamepad is {}", path.display()),
+            Ok(VR2UI::EngageUpdate(engaged)) => {
+                log::info!("Stick {}", if engaged { "engaged" } else { "released" })
+            }
+            Ok(VR2UI::TrackingUpdate(tracked, losses)) => {
+                if tracked {
+                    log::info!("Tracking regained");
+                } else {
+                    log::warn!("Tracking lost ({} times so far)", losses);
+                }
+            }
+            Ok(_) | Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                log::error!("Backend stopped unexpectedly");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> { Options::parse(args.iter().map(|arg| arg.to_string())) }
+
+    /// Writes `contents` to a profile of its own for each test.
+    fn profile(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("flightwand-test-{}-{name}.profile", std::process::id()));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn options_are_parsed() {
+        let options = parse(&[
+            "--headless",
+            "--hand",
+            "Left",
+            "--deadzone",
+            "0.2",
+            "--max",
+            "0.6",
+            "--smoothing",
+            "0.05",
+            "--up",
+            "0, 0.5,-1",
+            "--log-level",
+            "debug",
+        ])
+        .unwrap();
+        assert!(options.headless);
+        assert_eq!(options.hand.to_string(), "Left");
+        assert_eq!((options.bounds.deadzone, options.bounds.stick_max, options.bounds.smoothing), (0.2, 0.6, 0.05));
+        assert_eq!(options.up, [0.0, 0.5, -1.0]);
+        assert_eq!(options.log_level, LevelFilter::Debug);
+    }
+
+    #[test]
+    fn malformed_options_are_errors() {
+        for args in [
+            &["--deadzone", "1.5"][..],
+            &["--max", "abc"],
+            &["--smoothing", "-1"],
+            &["--up", "0,0.2"],
+            &["--up", "0,0.2,-1,3"],
+            &["--up", "0,x,-1"],
+            &["--up", "0,,-1"],
+            &["--hand", "middle"],
+            &["--log-level", "loud"],
+            &["--deadzone"],
+            &["--frobnicate", "1"],
+            &["headless"],
+        ] {
+            assert!(parse(args).is_err(), "{args:?}");
+        }
+    }
+
+    #[test]
+    fn profiles_are_read_and_later_flags_win() {
+        let path = profile("read", "# a comment\nheadless\nhand left\n\ndeadzone 0.3  # trailing\nmax 0.9\n");
+        let options = parse(&["--profile", path.to_str().unwrap(), "--max", "0.7"]).unwrap();
+        assert!(options.headless);
+        assert_eq!(options.hand.to_string(), "Left");
+        assert_eq!((options.bounds.deadzone, options.bounds.stick_max), (0.3, 0.7));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn malformed_profiles_are_errors() {
+        for (name, contents) in [
+            ("number", "deadzone lots\n"),
+            ("up", "up 1,2\n"),
+            ("unknown", "frobnicate 1\n"),
+            ("nested", "profile other.profile\n"),
+            ("export", "export-csv flight.fwrec\n"),
+            ("headless", "headless yes\n"),
+        ] {
+            let path = profile(name, contents);
+            let err = parse(&["--profile", path.to_str().unwrap()]).err();
+            assert!(err.is_some_and(|err| err.contains(path.to_str().unwrap())), "{contents}");
+            fs::remove_file(path).unwrap();
+        }
+        assert!(parse(&["--profile", "/nonexistent/flightwand.profile"]).is_err());
+    }
+}
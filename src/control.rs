@@ -0,0 +1,497 @@
+use crate::{
+    pipe::{GAMEPAD_BUTTONS, Hand, OutputState, UI2VR, VR2UI, VRInputBounds, VRTelemetry},
+    telemetry::TelemetryReader,
+};
+use serde_json::{Value, json};
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, mpsc},
+    time::Duration,
+};
+
+/// What the backend last told the frontend, and the frontend the backend, for answering `query`.
+struct State {
+    running: bool,
+    system_name: Option<String>,
+    failure: Option<String>,
+    rotation: [f32; 4],
+    up: [f32; 3],
+    bounds: VRInputBounds,
+    trim: [f32; 2],
+    engaged: bool,
+    tracked: bool,
+    tracking_losses: u32,
+    replaying: bool,
+    gamepad: OutputState,
+    /// The virtual gamepad's input device under /sys
+    device: Option<PathBuf>,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            running: false,
+            system_name: None,
+            failure: None,
+            rotation: [0.0, 0.0, 0.0, 1.0],
+            up: [-0.02, 0.2, -1.0],
+            bounds: VRInputBounds::default(),
+            trim: [0.0; 2],
+            engaged: true,
+            tracked: true,
+            tracking_losses: 0,
+            replaying: false,
+            gamepad: OutputState::default(),
+            device: None,
+        }
+    }
+}
+
+impl State {
+    fn to_json(&self) -> Value {
+        json!({
+            "running": self.running,
+            "system_name": self.system_name,
+            "failure": self.failure,
+            "rotation": self.rotation,
+            "up": self.up,
+            "bounds": {
+                "deadzone": self.bounds.deadzone,
+                "max": self.bounds.stick_max,
+                "smoothing": self.bounds.smoothing,
+                "ranges": self.bounds.ranges.map(|ranges| [ranges.right, ranges.left, ranges.forward, ranges.back]),
+            },
+            "trim": self.trim,
+            "engaged": self.engaged,
+            "tracked": self.tracked,
+            "tracking_losses": self.tracking_losses,
+            "replaying": self.replaying,
+            "gamepad": gamepad_json(&self.gamepad),
+            "device": self.device.as_ref().map(|path| path.display().to_string()),
+        })
+    }
+}
+
+/// Telemetry a client can subscribe to.
+#[derive(PartialEq, Copy, Clone)]
+enum Topic {
+    /// Controller orientation
+    Rotation,
+    /// Everything that changes what's sent to the game: trim, engagement, tracking, recentering
+    Output,
+    /// Axes and buttons as the game sees them
+    Gamepad,
+}
+
+impl Topic {
+    fn parse(name: &str) -> Option<Topic> {
+        match name {
+            "rotation" => Some(Topic::Rotation),
+            "output" => Some(Topic::Output),
+            "gamepad" => Some(Topic::Gamepad),
+            _ => None,
+        }
+    }
+}
+
+type Connection = Arc<Mutex<UnixStream>>;
+
+struct Subscriber {
+    topics: Vec<Topic>,
+    connection: Connection,
+}
+
+#[derive(Default)]
+struct Shared {
+    state: State,
+    subscribers: Vec<Subscriber>,
+    /// Where backend messages go on to, until the backend stops
+    frontend: Option<mpsc::Sender<VR2UI>>,
+}
+
+fn send_line(connection: &Connection, message: &Value) -> io::Result<()> {
+    let mut line = message.to_string();
+    line.push('\n');
+    connection.lock().unwrap().write_all(line.as_bytes())
+}
+
+fn gamepad_json(gamepad: &OutputState) -> Value {
+    let buttons: serde_json::Map<String, Value> = GAMEPAD_BUTTONS
+        .iter()
+        .zip(gamepad.buttons)
+        .map(|((_, key), pressed)| (format!("{key:?}"), Value::Bool(pressed)))
+        .collect();
+    json!({ "axes": gamepad.axes, "buttons": buttons, "live": gamepad.live })
+}
+
+/// Records `sample` in the state and turns it into an event for subscribers.
+fn observe_telemetry(state: &mut State, sample: &VRTelemetry) -> Option<(Topic, Value)> {
+    match sample {
+        VRTelemetry::Rotation(quat, _) => {
+            state.rotation = [quat.x, quat.y, quat.z, quat.w];
+            Some((Topic::Rotation, json!({ "event": "rotation", "rotation": state.rotation })))
+        }
+        VRTelemetry::ReplayProgress(_) | VRTelemetry::Input(_) => None,
+        VRTelemetry::Output(gamepad) => {
+            state.gamepad = *gamepad;
+            Some((Topic::Gamepad, json!({ "event": "gamepad", "gamepad": gamepad_json(gamepad) })))
+        }
+    }
+}
+
+/// The subscribers to `topic`, each with `event` to send them.
+fn recipients(shared: &Shared, topic: Topic, event: &Value) -> Vec<(Connection, Value)> {
+    shared
+        .subscribers
+        .iter()
+        .filter(|subscriber| subscriber.topics.contains(&topic))
+        .map(|subscriber| (subscriber.connection.clone(), event.clone()))
+        .collect()
+}
+
+/// Sends each event to its subscriber, dropping the ones that can't keep up. Writes can wait on a
+/// slow client, so this has to happen without `shared` locked.
+fn deliver(shared: &Mutex<Shared>, deliveries: Vec<(Connection, Value)>) {
+    let mut failed: Vec<Connection> = Vec::new();
+    for (connection, event) in deliveries {
+        if failed.iter().any(|other| Arc::ptr_eq(other, &connection)) {
+            continue;
+        }
+        if send_line(&connection, &event).is_err() {
+            failed.push(connection);
+        }
+    }
+    if !failed.is_empty() {
+        shared
+            .lock()
+            .unwrap()
+            .subscribers
+            .retain(|subscriber| !failed.iter().any(|other| Arc::ptr_eq(other, &subscriber.connection)));
+    }
+}
+
+/// Records what the frontend, or a client, told the backend.
+fn observe_command(state: &mut State, msg: &UI2VR) {
+    match msg {
+        UI2VR::UpdateBounds(bounds) => state.bounds = *bounds,
+        UI2VR::UpdateIdentity(up) => state.up = *up,
+        _ => {}
+    }
+}
+
+/// Records `msg` in the state and turns it into an event for subscribers.
+fn observe(state: &mut State, msg: &VR2UI) -> Option<(Topic, Value)> {
+    match msg {
+        VR2UI::Running(info) => {
+            state.running = true;
+            state.system_name = Some(info.system_properties.system_name.clone());
+            None
+        }
+        VR2UI::Failure(failure) => {
+            state.running = false;
+            state.failure = Some(failure.to_string());
+            None
+        }
+        VR2UI::TrimUpdate(trim) => {
+            state.trim = *trim;
+            Some((Topic::Output, json!({ "event": "trim", "trim": trim })))
+        }
+        VR2UI::IdentityUpdate(up) => {
+            state.up = *up;
+            Some((Topic::Output, json!({ "event": "recenter", "up": up })))
+        }
+        VR2UI::EngageUpdate(engaged) => {
+            state.engaged = *engaged;
+            Some((Topic::Output, json!({ "event": "engage", "engaged": engaged })))
+        }
+        VR2UI::TrackingUpdate(tracked, losses) => {
+            state.tracked = *tracked;
+            state.tracking_losses = *losses;
+            Some((Topic::Output, json!({ "event": "tracking", "tracked": tracked, "losses": losses })))
+        }
+        VR2UI::RecordingUpdate(path) => Some((
+            Topic::Output,
+            json!({ "event": "recording", "path": path.as_ref().map(|path| path.display().to_string()) }),
+        )),
+        VR2UI::ReplayUpdate(progress) => {
+            // progress comes in at telemetry rate, only starting and stopping are worth telling about
+            let replaying = progress.is_some();
+            if replaying == state.replaying {
+                return None;
+            }
+            state.replaying = replaying;
+            Some((Topic::Output, json!({ "event": "replay", "replaying": replaying })))
+        }
+        VR2UI::PivotUpdate(pivot) => Some((Topic::Output, json!({ "event": "pivot", "pivot": pivot }))),
+        VR2UI::GamepadDevice(path) => {
+            state.device = Some(path.clone());
+            None
+        }
+        VR2UI::BoundsUpdate(bounds) => {
+            state.bounds = *bounds;
+            None
+        }
+    }
+}
+
+fn number(command: &Value, key: &str) -> Result<f32, String> {
+    command.get(key).and_then(Value::as_f64).map(|v| v as f32).ok_or_else(|| format!("`{key}` needs a number"))
+}
+
+/// `key` if the command has it, which then has to be a number.
+fn optional_number(command: &Value, key: &str) -> Result<Option<f32>, String> {
+    command.get(key).map(|_| number(command, key)).transpose()
+}
+
+/// Tells the frontend about a change a client made, so it doesn't hold on to the old value.
+fn echo(shared: &Shared, msg: VR2UI) {
+    if let Some(frontend) = &shared.frontend {
+        let _ = frontend.send(msg);
+    }
+}
+
+/// Runs one command line from a client, returning the reply.
+fn handle(
+    line: &str,
+    tx: &mpsc::Sender<UI2VR>,
+    shared: &Mutex<Shared>,
+    connection: &Connection,
+) -> Result<Value, String> {
+    let command: Value = serde_json::from_str(line).map_err(|err| format!("invalid JSON: {err}"))?;
+    let name = command.get("cmd").and_then(Value::as_str).ok_or("missing `cmd`")?;
+
+    let msg = match name {
+        "start" => {
+            let hand = match command.get("hand").and_then(Value::as_str).unwrap_or("right") {
+                "left" => Hand::Left,
+                "right" => Hand::Right,
+                other => return Err(format!("unknown hand `{other}`")),
+            };
+            UI2VR::Start(hand)
+        }
+        "recenter" => UI2VR::Recenter,
+        "update_bounds" => {
+            let (deadzone, max, smoothing) = (
+                optional_number(&command, "deadzone")?,
+                optional_number(&command, "max")?,
+                optional_number(&command, "smoothing")?,
+            );
+            if deadzone.is_none() && max.is_none() && smoothing.is_none() {
+                return Err("`update_bounds` needs `deadzone`, `max` or `smoothing`".to_owned());
+            }
+            // only what's given changes, the rest stays as the backend has it
+            let mut shared = shared.lock().unwrap();
+            let mut bounds = shared.state.bounds;
+            bounds.deadzone = deadzone.unwrap_or(bounds.deadzone);
+            bounds.smoothing = smoothing.unwrap_or(bounds.smoothing);
+            if let Some(max) = max {
+                // one maximum replaces the calibrated ones for each direction
+                bounds.stick_max = max;
+                bounds.ranges = None;
+            }
+            bounds.check()?;
+            shared.state.bounds = bounds;
+            echo(&shared, VR2UI::BoundsUpdate(bounds));
+            UI2VR::UpdateBounds(bounds)
+        }
+        "update_identity" => {
+            let up = command.get("up").and_then(Value::as_array).ok_or("`up` needs three numbers")?;
+            let up: Option<Vec<f32>> = up.iter().map(|v| v.as_f64().map(|v| v as f32)).collect();
+            let up: [f32; 3] = up.and_then(|up| up.try_into().ok()).ok_or("`up` needs three numbers")?;
+            let mut shared = shared.lock().unwrap();
+            shared.state.up = up;
+            echo(&shared, VR2UI::IdentityUpdate(up));
+            UI2VR::UpdateIdentity(up)
+        }
+        "reset_trim" => UI2VR::ResetTrim,
+        "start_recording" => UI2VR::StartRecording,
+        "stop_recording" => UI2VR::StopRecording,
+        "query" => return Ok(json!({ "ok": true, "state": shared.lock().unwrap().state.to_json() })),
+        "subscribe" => {
+            let topics = command.get("topics").and_then(Value::as_array).ok_or("`topics` needs a list")?;
+            let topics = topics
+                .iter()
+                .map(|topic| topic.as_str().and_then(Topic::parse).ok_or(format!("unknown topic {topic}")))
+                .collect::<Result<Vec<_>, _>>()?;
+            let mut shared = shared.lock().unwrap();
+            shared.subscribers.retain(|subscriber| !Arc::ptr_eq(&subscriber.connection, connection));
+            shared.subscribers.push(Subscriber { topics, connection: connection.clone() });
+            return Ok(json!({ "ok": true }));
+        }
+        other => return Err(format!("unknown command `{other}`")),
+    };
+
+    tx.send(msg).map_err(|_| "backend has stopped".to_owned())?;
+    Ok(json!({ "ok": true }))
+}
+
+fn serve_client(stream: UnixStream, tx: mpsc::Sender<UI2VR>, shared: Arc<Mutex<Shared>>) -> io::Result<()> {
+    // a client that stops reading shouldn't hold up telemetry for everyone else
+    stream.set_write_timeout(Some(Duration::from_millis(100)))?;
+    let connection = Arc::new(Mutex::new(stream.try_clone()?));
+
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = handle(&line, &tx, &shared, &connection).unwrap_or_else(|err| json!({ "ok": false, "error": err }));
+        send_line(&connection, &reply)?;
+    }
+
+    shared.lock().unwrap().subscribers.retain(|subscriber| !Arc::ptr_eq(&subscriber.connection, &connection));
+    Ok(())
+}
+
+fn bind(path: &Path) -> io::Result<UnixListener> {
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(io::ErrorKind::AddrInUse, "another FlightWand is already listening"));
+        }
+        // left behind by an instance that didn't shut down cleanly
+        std::fs::remove_file(path)?;
+    }
+    UnixListener::bind(path)
+}
+
+/// Opens the control socket at `path`, sitting between the backend and the frontend. Every message
+/// still gets through the returned sender and receiver; the socket only watches them go by.
+pub fn serve(
+    path: PathBuf,
+    tx: mpsc::Sender<UI2VR>,
+    rx: mpsc::Receiver<VR2UI>,
+    mut telemetry: TelemetryReader<VRTelemetry>,
+) -> (mpsc::Sender<UI2VR>, mpsc::Receiver<VR2UI>) {
+    let (frontend_tx, frontend_rx) = mpsc::channel();
+    let (command_tx, command_rx) = mpsc::channel();
+    let shared = Arc::new(Mutex::new(Shared { frontend: Some(frontend_tx), ..Shared::default() }));
+
+    let command_shared = shared.clone();
+    std::thread::Builder::new()
+        .name("Control commands".to_owned())
+        .spawn(move || {
+            while let Ok(msg) = command_rx.recv() {
+                observe_command(&mut command_shared.lock().unwrap().state, &msg);
+                if tx.send(msg).is_err() {
+                    break;
+                }
+            }
+        })
+        .expect("Failed to spawn the control command thread");
+
+    let bound = match bind(&path) {
+        Ok(listener) => {
+            log::info!("Control socket listening on {}", path.display());
+            let (tx, shared) = (command_tx.clone(), shared.clone());
+            std::thread::Builder::new()
+                .name("Control socket".to_owned())
+                .spawn(move || {
+                    for stream in listener.incoming() {
+                        let Ok(stream) = stream else { continue };
+                        let (tx, shared) = (tx.clone(), shared.clone());
+                        let _ = std::thread::Builder::new()
+                            .name("Control client".to_owned())
+                            .spawn(move || serve_client(stream, tx, shared));
+                    }
+                })
+                .expect("Failed to spawn the control socket thread");
+            true
+        }
+        Err(err) => {
+            log::warn!("Couldn't open the control socket at {}: {}", path.display(), err);
+            false
+        }
+    };
+
+    let relay_shared = shared.clone();
+    std::thread::Builder::new()
+        .name("Control telemetry".to_owned())
+        .spawn(move || {
+            while let Some(samples) = telemetry.wait(Duration::from_secs(1)) {
+                let mut deliveries = Vec::new();
+                let mut shared = relay_shared.lock().unwrap();
+                for sample in &samples {
+                    if let Some((topic, event)) = observe_telemetry(&mut shared.state, sample) {
+                        deliveries.extend(recipients(&shared, topic, &event));
+                    }
+                }
+                drop(shared);
+                deliver(&relay_shared, deliveries);
+            }
+        })
+        .expect("Failed to spawn the control telemetry thread");
+
+    std::thread::Builder::new()
+        .name("Control relay".to_owned())
+        .spawn(move || {
+            while let Ok(msg) = rx.recv() {
+                let mut locked = shared.lock().unwrap();
+                let deliveries = match observe(&mut locked.state, &msg) {
+                    Some((topic, event)) => recipients(&locked, topic, &event),
+                    None => Vec::new(),
+                };
+                let frontend = locked.frontend.clone();
+                drop(locked);
+                deliver(&shared, deliveries);
+
+                if frontend.is_none_or(|frontend| frontend.send(msg).is_err()) {
+                    break;
+                }
+            }
+            // the frontend sees the backend stop once nothing can send to it
+            shared.lock().unwrap().frontend = None;
+            if bound {
+                let _ = std::fs::remove_file(&path);
+            }
+        })
+        .expect("Failed to spawn the control relay thread");
+
+    (command_tx, frontend_rx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs `line` against `shared`, returning the reply and what reached the backend.
+    fn run(shared: &Mutex<Shared>, line: &str) -> (Result<Value, String>, Option<UI2VR>) {
+        let (tx, rx) = mpsc::channel();
+        let (connection, _client) = UnixStream::pair().unwrap();
+        let reply = handle(line, &tx, shared, &Arc::new(Mutex::new(connection)));
+        (reply, rx.try_recv().ok())
+    }
+
+    #[test]
+    fn update_bounds_merges_what_is_given() {
+        let shared = Mutex::new(Shared::default());
+        let (reply, msg) = run(&shared, r#"{ "cmd": "update_bounds", "deadzone": 0.2 }"#);
+        assert!(reply.is_ok());
+        let Some(UI2VR::UpdateBounds(bounds)) = msg else { panic!("bounds weren't sent") };
+        assert_eq!((bounds.deadzone, bounds.stick_max), (0.2, VRInputBounds::default().stick_max));
+
+        run(&shared, r#"{ "cmd": "update_bounds", "max": 0.5 }"#).0.unwrap();
+        let bounds = shared.lock().unwrap().state.bounds;
+        assert_eq!((bounds.deadzone, bounds.stick_max), (0.2, 0.5));
+    }
+
+    #[test]
+    fn update_bounds_rejects_what_the_command_line_would() {
+        let shared = Mutex::new(Shared::default());
+        for line in [
+            r#"{ "cmd": "update_bounds", "deadzone": 1.5 }"#,
+            r#"{ "cmd": "update_bounds", "max": -0.1 }"#,
+            r#"{ "cmd": "update_bounds", "deadzone": 0.5, "max": 0.3 }"#,
+            r#"{ "cmd": "update_bounds", "max": 0.05 }"#,
+            r#"{ "cmd": "update_bounds", "smoothing": -1 }"#,
+            r#"{ "cmd": "update_bounds", "deadzone": "0.1" }"#,
+        ] {
+            let (reply, msg) = run(&shared, line);
+            assert!(reply.is_err(), "{line}");
+            assert!(msg.is_none(), "{line}");
+        }
+        let bounds = shared.lock().unwrap().state.bounds;
+        assert_eq!((bounds.deadzone, bounds.stick_max), (0.1, 0.85));
+    }
+}
@@ -0,0 +1,16 @@
+//! The parts of FlightWand that work without a VR session or a window.
+
+pub mod mapping;
+pub mod pipe;
+pub mod recording;
+pub mod util;
+
+use std::path::PathBuf;
+
+/// Where FlightWand's control socket lives unless told otherwise.
+pub fn default_socket_path() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir).join("flightwand.sock"),
+        None => std::env::temp_dir().join(format!("flightwand-{}.sock", unsafe { libc::getuid() })),
+    }
+}
@@ -1,19 +1,53 @@
 use crate::{
+    cli::Options,
     pipe::{UI2VR, VR2UI},
+    telemetry::Telemetry,
     ui::UI,
     vrclient::VRClient,
 };
+use std::process::ExitCode;
 
-pub mod pipe;
+use flightwand::{mapping, pipe, recording, util};
+
+mod cli;
+mod control;
+mod telemetry;
 mod ui;
-pub mod util;
 mod vrclient;
 
+/// Telemetry samples each reader can fall behind by before the oldest are dropped. Rotation, input
+/// and output each come about every 11 ms, so this is about a second and a half
+const TELEMETRY_QUEUE: usize = 3 * 140;
+
 #[profiling::function]
-pub fn main() -> eframe::Result {
+pub fn main() -> ExitCode {
+    let options = match Options::from_args() {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}", message);
+            return ExitCode::FAILURE;
+        }
+    };
+    util::logger::init_logger(options.log_level);
+
     let (ui_tx, vr_rx) = std::sync::mpsc::channel::<UI2VR>();
     let (vr_tx, ui_rx) = std::sync::mpsc::channel::<VR2UI>();
 
-    VRClient::run(vr_tx, vr_rx);
-    UI::run(ui_tx, ui_rx)
+    let telemetry = Telemetry::new();
+    let ui_telemetry = telemetry.subscribe(TELEMETRY_QUEUE);
+    let control_telemetry = telemetry.subscribe(TELEMETRY_QUEUE);
+
+    VRClient::run(vr_tx, vr_rx, telemetry);
+    let (ui_tx, ui_rx) = control::serve(options.socket.clone(), ui_tx, ui_rx, control_telemetry);
+    if options.headless {
+        return cli::run_headless(options, ui_tx, ui_rx);
+    }
+
+    match UI::run(ui_tx, ui_rx, ui_telemetry, &options) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{}", err);
+            ExitCode::FAILURE
+        }
+    }
 }
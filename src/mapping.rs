@@ -0,0 +1,484 @@
+//! Turns a controller pose and its inputs into gamepad output. Nothing here touches OpenXR or the
+//! virtual device, so the backend and the frontend can share it and it can be tested on its own.
+//!
+//! Trim, the clutch and the tracking failsafe keep state between frames and live with the output
+//! thread; they're applied to [`GamepadState::stick`] before [`to_axes`].
+
+use crate::pipe::{ControllerButton, GAMEPAD_BUTTONS, GimbalSettings, VRInputBounds};
+
+// Trackpad sector boundaries, in half-turns from the top of the pad
+pub const NE: f32 = 0.25;
+pub const NW: f32 = -0.25;
+pub const SE: f32 = 0.75;
+pub const SW: f32 = -0.75;
+
+/// How far from the center the trackpad has to be clicked to count as a direction
+pub const TRACKPAD_DIRECTION_DISTANCE: f32 = 0.35;
+/// How far the trigger has to be pulled to count as a button press
+pub const TRIGGER_PRESS: f32 = 0.75;
+
+/// Every bound controller input, sampled once per frame.
+#[derive(Clone, Copy, Default)]
+pub struct ControllerState {
+    pub trigger: f32,
+    pub trackpad: [f32; 2],
+    pub trackpad_click: bool,
+    pub grip: bool,
+    pub menu: bool,
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum TrackpadDirection {
+    Up,
+    Right,
+    Down,
+    Left,
+}
+
+impl ControllerState {
+    /// The trackpad sector being clicked, if any.
+    pub fn trackpad_direction(&self) -> Option<TrackpadDirection> {
+        let [x, y] = self.trackpad;
+        let ang = f32::atan2(x, y) / std::f32::consts::PI;
+        let distance = f32::sqrt(x * x + y * y);
+
+        if distance <= TRACKPAD_DIRECTION_DISTANCE || !self.trackpad_click {
+            None
+        } else if ang < NE && ang > NW {
+            Some(TrackpadDirection::Up)
+        } else if ang < SE && ang > NE {
+            Some(TrackpadDirection::Right)
+        } else if ang < NW && ang > SW {
+            Some(TrackpadDirection::Left)
+        } else if !(SW..=SE).contains(&ang) {
+            Some(TrackpadDirection::Down)
+        } else {
+            None
+        }
+    }
+
+    pub fn pressed(&self, button: ControllerButton) -> bool {
+        match button {
+            ControllerButton::None => false,
+            ControllerButton::TrackpadClick => self.trackpad_click,
+            ControllerButton::TrackpadUp => self.trackpad_direction() == Some(TrackpadDirection::Up),
+            ControllerButton::TrackpadDown => self.trackpad_direction() == Some(TrackpadDirection::Down),
+            ControllerButton::TrackpadLeft => self.trackpad_direction() == Some(TrackpadDirection::Left),
+            ControllerButton::TrackpadRight => self.trackpad_direction() == Some(TrackpadDirection::Right),
+            ControllerButton::Grip => self.grip,
+            ControllerButton::Menu => self.menu,
+            ControllerButton::Trigger => self.trigger > TRIGGER_PRESS,
+        }
+    }
+
+    /// Whether `button` went down between `previous` and this frame.
+    pub fn just_pressed(&self, previous: &ControllerState, button: ControllerButton) -> bool {
+        self.pressed(button) && !previous.pressed(button)
+    }
+}
+
+/// Where the controller is, in the frame the stick is measured in.
+#[derive(Clone, Copy)]
+pub struct Pose {
+    /// `x, y, z, w`
+    pub orientation: [f32; 4],
+    /// In meters, `None` while the position isn't known
+    pub position: Option<[f32; 3]>,
+}
+
+/// What one frame of input maps to, before trim and the clutch.
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct GamepadState {
+    /// `-1.0..=1.0` at the maximum ring, but not clamped to it
+    pub stick: [f32; 2],
+    /// How far the stick is pushed before the deadzone and scaling, for the haptic cues
+    pub deflection: f32,
+    /// Past the maximum in the direction it's pushed
+    pub saturated: bool,
+    /// Pressed, in the order of [`GAMEPAD_BUTTONS`]
+    pub buttons: [bool; GAMEPAD_BUTTONS.len()],
+}
+
+/// Direction of `up` once rotated by `input`, with an out of range fourth coordinate so it can go
+/// straight into the graphs. `None` when `up` doesn't point anywhere.
+#[profiling::function]
+pub fn modifier(input: &[f32; 4], up: [f32; 3]) -> Option<[f32; 4]> {
+    let id = nalgebra::Quaternion::from_imag(nalgebra::Vector3::from(up).try_normalize(f32::EPSILON)?);
+    let quat = nalgebra::Quaternion::new(input[3], input[0], input[1], input[2]);
+    let res = quat * id * quat.conjugate();
+    let res = res.coords.try_normalize(f32::EPSILON).filter(|res| res.iter().all(|v| v.is_finite()))?;
+
+    // we don't use the fourth coordinate for anything this function touches,
+    // set it to something out of the graph's bounds
+    Some([res.x, res.y, res.z, -2.0])
+}
+
+/// Direction of a virtual stick from `pivot` to `position`, in the same form as [`modifier`]'s output.
+/// `None` when the controller sits on the pivot.
+#[profiling::function]
+pub fn gimbal(position: [f32; 3], pivot: [f32; 3], orientation: [f32; 4], orientation_blend: f32) -> Option<[f32; 4]> {
+    let stick = (nalgebra::Vector3::from(position) - nalgebra::Vector3::from(pivot)).try_normalize(f32::EPSILON)?;
+    let wrist = nalgebra::Vector3::new(orientation[0], orientation[1], orientation[2]);
+    let blend = orientation_blend.clamp(0.0, 1.0);
+    let res = stick.lerp(&wrist, blend).try_normalize(f32::EPSILON)?;
+
+    Some([res.x, res.y, res.z, -2.0])
+}
+
+#[profiling::function]
+pub fn rot_to_joy(input: &[f32; 2]) -> [f32; 2] {
+    let x = f32::sin(input[0]);
+    let y = f32::sin(-input[1]);
+    [x, y]
+}
+
+/// How far the controller has to lean from neutral along one axis for the stick to read
+/// `deflection`, in radians. A quarter turn when leaning alone can't get there.
+pub fn tilt_for(deflection: f32) -> f32 {
+    // the direction leans over by sin(tilt), and the stick is the sine of that
+    let reach = deflection.abs().min(1.0).asin();
+    if reach > 1.0 { std::f32::consts::FRAC_PI_2 } else { reach.asin() }
+}
+
+/// Scales a stick value to what the virtual gamepad takes, clamping it to the axis range.
+pub fn to_axes(stick: [f32; 2]) -> [i32; 2] {
+    stick.map(|v| {
+        // `as` would turn NaN into 0 too, but say so
+        if v.is_nan() { 0 } else { (v.clamp(-1.0, 1.0) * i16::MAX as f32) as i32 }
+    })
+}
+
+/// The stateless part of turning the controller into a stick.
+#[derive(Clone, Copy)]
+pub struct StickPipeline {
+    /// Controller direction that counts as neutral
+    pub up: [f32; 3],
+    pub bounds: VRInputBounds,
+    pub gimbal: GimbalSettings,
+}
+
+impl StickPipeline {
+    /// Where the stick points, in the same form as [`modifier`]'s output. Straight up when that
+    /// can't be worked out.
+    pub fn direction(&self, pose: &Pose) -> [f32; 4] {
+        let Some(rot) = modifier(&pose.orientation, self.up) else { return [0.0, 1.0, 0.0, -2.0] };
+        match pose.position {
+            Some(position) if self.gimbal.enabled => {
+                gimbal(position, self.gimbal.pivot, rot, self.gimbal.orientation_blend).unwrap_or(rot)
+            }
+            _ => rot,
+        }
+    }
+
+    /// Maps `pose` to the stick, and forwards whatever in `input` isn't `claimed` for something else.
+    pub fn map(
+        &self,
+        pose: &Pose,
+        input: &ControllerState,
+        claimed: impl Fn(ControllerButton) -> bool,
+    ) -> GamepadState {
+        self.map_deflection(self.deflection(pose), input, claimed)
+    }
+
+    /// Where `pose` pushes the stick, before the deadzone and scaling.
+    pub fn deflection(&self, pose: &Pose) -> [f32; 2] {
+        let rot = self.direction(pose);
+        rot_to_joy(&[rot[0], rot[2]])
+    }
+
+    /// Like [`map`](Self::map), from a [`deflection`](Self::deflection) that's been filtered first.
+    pub fn map_deflection(
+        &self,
+        [x, y]: [f32; 2],
+        input: &ControllerState,
+        claimed: impl Fn(ControllerButton) -> bool,
+    ) -> GamepadState {
+        let deflection = f32::sqrt(x * x + y * y);
+
+        let [max_x, max_y] = self.bounds.max_for([x, y]);
+        let stick = if deflection < self.bounds.deadzone { [0.0, 0.0] } else { [x / max_x, y / max_y] };
+        let saturated = f32::sqrt(stick[0] * stick[0] + stick[1] * stick[1]) >= 1.0;
+        let buttons = GAMEPAD_BUTTONS.map(|(button, _)| input.pressed(button) && !claimed(button));
+
+        GamepadState { stick, deflection, saturated, buttons }
+    }
+}
+
+/// Smooths the stick's deflection over time, to keep tracking noise out of the deadzone.
+#[derive(Clone, Copy, Default)]
+pub struct StickFilter {
+    last: Option<[f32; 2]>,
+}
+
+impl StickFilter {
+    /// Moves towards `deflection` with a time constant of `smoothing` seconds, `dt` after the last one.
+    pub fn apply(&mut self, deflection: [f32; 2], smoothing: f32, dt: f32) -> [f32; 2] {
+        let filtered = match self.last {
+            Some(last) if smoothing > 0.0 && deflection.iter().all(|v| v.is_finite()) => {
+                let alpha = 1.0 - f32::exp(-dt / smoothing);
+                [0, 1].map(|i| last[i] + (deflection[i] - last[i]) * alpha)
+            }
+            _ => deflection,
+        };
+        // something unusable starts over instead of sticking around
+        self.last = filtered.iter().all(|v| v.is_finite()).then_some(filtered);
+        filtered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipe::StickRanges;
+    use std::f32::consts::{FRAC_PI_2, PI};
+
+    const UP: [f32; 3] = [0.0, 1.0, 0.0];
+
+    fn pipeline(up: [f32; 3]) -> StickPipeline {
+        StickPipeline { up, bounds: VRInputBounds::default(), gimbal: GimbalSettings::default() }
+    }
+
+    fn pose(orientation: nalgebra::UnitQuaternion<f32>) -> Pose {
+        let q = orientation.into_inner();
+        Pose { orientation: [q.i, q.j, q.k, q.w], position: None }
+    }
+
+    fn euler(roll: f32, pitch: f32, yaw: f32) -> Pose {
+        pose(nalgebra::UnitQuaternion::from_euler_angles(roll, pitch, yaw))
+    }
+
+    fn nothing_claimed(_: ControllerButton) -> bool { false }
+
+    /// Deterministic orientations spread over the whole sphere, so failures can be reproduced.
+    fn orientations(count: usize) -> impl Iterator<Item = Pose> {
+        let mut seed = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed >> 40) as f32 / (1u64 << 24) as f32 * 2.0 - 1.0
+        };
+        (0..count).map(move |_| {
+            let q = nalgebra::Quaternion::new(next(), next(), next(), next());
+            pose(nalgebra::UnitQuaternion::from_quaternion(q))
+        })
+    }
+
+    #[test]
+    fn neutral_is_centered() {
+        let state = pipeline(UP).map(&euler(0.0, 0.0, 0.0), &ControllerState::default(), nothing_claimed);
+        assert_eq!(state.stick, [0.0, 0.0]);
+        assert!(state.deflection.abs() < 1e-6);
+    }
+
+    #[test]
+    fn yaw_alone_stays_centered() {
+        for step in 0..36 {
+            let yaw = step as f32 / 36.0 * 2.0 * PI;
+            let state = pipeline(UP).map(&euler(0.0, yaw, 0.0), &ControllerState::default(), nothing_claimed);
+            assert_eq!(state.stick, [0.0, 0.0], "yaw {yaw}");
+        }
+    }
+
+    #[test]
+    fn tilting_moves_the_stick_the_right_way() {
+        let p = pipeline(UP);
+        // tilting the top of the controller to the right, around the forward axis
+        let right = p.map(&euler(0.0, 0.0, -0.5), &ControllerState::default(), nothing_claimed);
+        assert!(right.stick[0] > 0.0 && right.stick[1].abs() < 1e-6, "{:?}", right.stick);
+        // tilting it forward, around the right axis
+        let forward = p.map(&euler(-0.5, 0.0, 0.0), &ControllerState::default(), nothing_claimed);
+        assert!(forward.stick[1] > 0.0 && forward.stick[0].abs() < 1e-6, "{:?}", forward.stick);
+    }
+
+    #[test]
+    fn deadzone_zeroes_small_tilts() {
+        let p = pipeline(UP);
+        let small = p.map(&euler(0.0, 0.0, 0.05), &ControllerState::default(), nothing_claimed);
+        assert!(small.deflection > 0.0 && small.deflection < p.bounds.deadzone);
+        assert_eq!(small.stick, [0.0, 0.0]);
+    }
+
+    #[test]
+    fn maximum_ring_is_full_output() {
+        let mut p = pipeline(UP);
+        p.bounds.stick_max = 0.5;
+        let tilt = tilt_for(p.bounds.stick_max);
+        let state = p.map(&euler(0.0, 0.0, -tilt), &ControllerState::default(), nothing_claimed);
+        assert!((state.stick[0] - 1.0).abs() < 1e-3, "{:?}", state.stick);
+        assert_eq!(to_axes(state.stick)[0], i16::MAX as i32);
+    }
+
+    #[test]
+    fn ranges_scale_each_side_separately() {
+        let mut p = pipeline(UP);
+        p.bounds.ranges = Some(StickRanges { right: 0.5, left: 0.3, forward: 0.6, back: 0.4 });
+        let right = p.map(&euler(0.0, 0.0, -tilt_for(0.5)), &ControllerState::default(), nothing_claimed);
+        let left = p.map(&euler(0.0, 0.0, tilt_for(0.3)), &ControllerState::default(), nothing_claimed);
+        assert!((right.stick[0] - 1.0).abs() < 1e-3, "{:?}", right.stick);
+        assert!((left.stick[0] + 1.0).abs() < 1e-3, "{:?}", left.stick);
+
+        let halfway = p.map(&euler(0.0, 0.0, tilt_for(0.15)), &ControllerState::default(), nothing_claimed);
+        assert!((halfway.stick[0] + 0.5).abs() < 1e-3, "{:?}", halfway.stick);
+        assert!(!halfway.saturated);
+    }
+
+    #[test]
+    fn tilt_for_tops_out_past_what_leaning_reaches() {
+        assert_eq!(tilt_for(0.0), 0.0);
+        assert_eq!(tilt_for(0.85), FRAC_PI_2);
+        assert!(tilt_for(0.84) < FRAC_PI_2);
+    }
+
+    #[test]
+    fn filter_settles_on_a_held_deflection() {
+        let mut filter = StickFilter::default();
+        assert_eq!(filter.apply([0.0, 0.0], 0.05, 0.004), [0.0, 0.0]);
+        let first = filter.apply([0.5, -0.5], 0.05, 0.004);
+        assert!(first[0] > 0.0 && first[0] < 0.1, "{first:?}");
+        let mut last = first;
+        for _ in 0..1000 {
+            last = filter.apply([0.5, -0.5], 0.05, 0.004);
+        }
+        assert!((last[0] - 0.5).abs() < 1e-4 && (last[1] + 0.5).abs() < 1e-4, "{last:?}");
+        // no smoothing passes straight through
+        assert_eq!(filter.apply([0.1, 0.2], 0.0, 0.004), [0.1, 0.2]);
+    }
+
+    #[test]
+    fn zero_up_doesnt_produce_nan() {
+        for pose in orientations(100) {
+            let state = pipeline([0.0; 3]).map(&pose, &ControllerState::default(), nothing_claimed);
+            assert_eq!(state.stick, [0.0, 0.0]);
+        }
+    }
+
+    #[test]
+    fn zero_stick_max_doesnt_produce_nan() {
+        let mut p = pipeline(UP);
+        p.bounds.stick_max = 0.0;
+        for pose in orientations(100) {
+            let state = p.map(&pose, &ControllerState::default(), nothing_claimed);
+            assert!(state.stick.iter().all(|v| !v.is_nan()), "{:?}", state.stick);
+        }
+    }
+
+    #[test]
+    fn gimbal_on_the_pivot_falls_back_to_orientation() {
+        let mut p = pipeline(UP);
+        p.gimbal.enabled = true;
+        let mut pose = euler(0.0, 0.0, -0.5);
+        let without = p.map(&pose, &ControllerState::default(), nothing_claimed);
+        pose.position = Some(p.gimbal.pivot);
+        let with = p.map(&pose, &ControllerState::default(), nothing_claimed);
+        assert_eq!(with, without);
+    }
+
+    #[test]
+    fn gimbal_follows_position() {
+        let mut p = pipeline(UP);
+        p.gimbal.enabled = true;
+        // straight above the pivot is neutral no matter how the controller is held
+        let mut pose = euler(0.0, 0.0, -1.0);
+        pose.position = Some([0.0, 0.3, 0.0]);
+        assert_eq!(p.map(&pose, &ControllerState::default(), nothing_claimed).stick, [0.0, 0.0]);
+        // off to the right of it is right
+        pose.position = Some([0.2, 0.3, 0.0]);
+        assert!(p.map(&pose, &ControllerState::default(), nothing_claimed).stick[0] > 0.0);
+    }
+
+    #[test]
+    fn output_is_finite_and_bounded_everywhere() {
+        let ups = [UP, [-0.02, 0.2, -1.0], [1.0, 0.0, 0.0], [0.0, 0.0, 0.0], [f32::NAN, 1.0, 0.0]];
+        for up in ups {
+            let p = pipeline(up);
+            for pose in orientations(2000) {
+                let state = p.map(&pose, &ControllerState::default(), nothing_claimed);
+                assert!(state.stick.iter().all(|v| v.is_finite()), "up {up:?}: {:?}", state.stick);
+                // sin of a unit vector component stays within sin(1)
+                assert!(state.deflection <= 2f32.sqrt() * 1f32.sin() + 1e-5);
+                for axis in to_axes(state.stick) {
+                    assert!((i16::MIN as i32..=i16::MAX as i32).contains(&axis));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn direction_is_a_unit_vector() {
+        let p = pipeline([-0.02, 0.2, -1.0]);
+        for pose in orientations(2000) {
+            let [x, y, z, w] = p.direction(&pose);
+            assert!(((x * x + y * y + z * z).sqrt() - 1.0).abs() < 1e-4);
+            assert_eq!(w, -2.0);
+        }
+    }
+
+    #[test]
+    fn stick_is_odd_under_mirrored_tilts() {
+        let p = pipeline(UP);
+        for step in 1..50 {
+            let angle = step as f32 / 50.0 * FRAC_PI_2;
+            let a = p.map(&euler(angle, 0.0, angle / 2.0), &ControllerState::default(), nothing_claimed);
+            let b = p.map(&euler(-angle, 0.0, -angle / 2.0), &ControllerState::default(), nothing_claimed);
+            assert!((a.stick[0] + b.stick[0]).abs() < 1e-4 && (a.stick[1] + b.stick[1]).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn to_axes_clamps_and_scales() {
+        assert_eq!(to_axes([0.0, 1.0]), [0, i16::MAX as i32]);
+        assert_eq!(to_axes([-1.0, 0.5]), [-(i16::MAX as i32), (0.5 * i16::MAX as f32) as i32]);
+        assert_eq!(to_axes([3.0, -7.0]), [i16::MAX as i32, -(i16::MAX as i32)]);
+        assert_eq!(to_axes([f32::NAN, f32::INFINITY]), [0, i16::MAX as i32]);
+    }
+
+    #[test]
+    fn trackpad_sectors() {
+        let click = |x, y| ControllerState { trackpad: [x, y], trackpad_click: true, ..Default::default() };
+        assert_eq!(click(0.0, 0.9).trackpad_direction(), Some(TrackpadDirection::Up));
+        assert_eq!(click(0.9, 0.0).trackpad_direction(), Some(TrackpadDirection::Right));
+        assert_eq!(click(0.0, -0.9).trackpad_direction(), Some(TrackpadDirection::Down));
+        assert_eq!(click(-0.9, 0.0).trackpad_direction(), Some(TrackpadDirection::Left));
+        // too close to the center
+        assert_eq!(click(0.1, 0.1).trackpad_direction(), None);
+        // touched, not clicked
+        let touch = ControllerState { trackpad: [0.0, 0.9], ..Default::default() };
+        assert_eq!(touch.trackpad_direction(), None);
+    }
+
+    #[test]
+    fn every_click_outside_the_center_has_exactly_one_direction() {
+        for step in 0..360 {
+            let angle = (step as f32 + 0.5).to_radians();
+            let input = ControllerState {
+                trackpad: [angle.sin() * 0.8, angle.cos() * 0.8],
+                trackpad_click: true,
+                ..Default::default()
+            };
+            let directions = [
+                ControllerButton::TrackpadUp,
+                ControllerButton::TrackpadRight,
+                ControllerButton::TrackpadDown,
+                ControllerButton::TrackpadLeft,
+            ];
+            assert_eq!(directions.iter().filter(|&&button| input.pressed(button)).count(), 1, "angle {angle}");
+        }
+    }
+
+    #[test]
+    fn claimed_buttons_dont_reach_the_gamepad() {
+        let input = ControllerState { grip: true, menu: true, ..Default::default() };
+        let state = pipeline(UP).map(&euler(0.0, 0.0, 0.0), &input, |button| button == ControllerButton::Grip);
+        for ((button, _), pressed) in GAMEPAD_BUTTONS.iter().zip(state.buttons) {
+            assert_eq!(pressed, *button == ControllerButton::Menu, "{button}");
+        }
+    }
+
+    #[test]
+    fn trigger_press_threshold() {
+        let pulled = |trigger| ControllerState { trigger, ..Default::default() }.pressed(ControllerButton::Trigger);
+        assert!(!pulled(0.5));
+        assert!(pulled(0.9));
+    }
+}
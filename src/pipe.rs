@@ -1,6 +1,8 @@
+use crate::{recording::Frame, util::runtime::Runtime};
 use ash::LoadingError;
+use evdev::KeyCode;
 use openxr::{Quaternionf, SystemProperties};
-use std::fmt::Display;
+use std::{fmt::Display, path::PathBuf, str::FromStr};
 
 #[derive(PartialEq, Copy, Clone)]
 pub enum Hand {
@@ -20,14 +22,63 @@ impl Display for Hand {
 pub enum UI2VR {
     /// Shuts the background down.
     Shutdown,
+    /// Picks the OpenXR runtime to start, `None` uses the active one. Only read before `Start`.
+    SelectRuntime(Option<Runtime>),
     /// Starts OpenXR.
     Start(Hand),
     /// Updates the backend's knowledge of "up"
     UpdateIdentity([f32; 3]),
     /// Updates the backend's knowledge of the stick bounds/parameters
     UpdateBounds(VRInputBounds),
+    /// Updates the trim step size and button assignments
+    UpdateTrim(TrimSettings),
+    /// Returns the trim offset to zero
+    ResetTrim,
+    /// Updates how the stick is engaged and what it does when released
+    UpdateEngage(EngageSettings),
+    /// Captures the controller's current orientation as neutral
+    Recenter,
+    /// Updates which haptic events are played, and how
+    UpdateHaptics(HapticSettings),
+    /// Plays a single haptic pulse on the controller
+    TestHaptic(HapticPulse),
+    /// Updates how game rumble is forwarded to the controller
+    UpdateForceFeedback(ForceFeedbackSettings),
+    /// Updates how many times per second the stick is sampled and sent to the game
+    UpdateOutputRate(u32),
+    /// Updates the keyboard and mouse output
+    UpdateKeyboardMouse(KeyboardMouseSettings),
+    /// Updates which pose is tracked and what it's measured against, recapturing neutral
+    UpdateTracking(TrackingSettings),
+    /// Uses the headset's current heading as the seat's forward direction
+    CaptureSeat,
+    /// Updates the virtual gimbal
+    UpdateGimbal(GimbalSettings),
+    /// Places the gimbal pivot one stick length below the controller
+    CapturePivot,
+    /// Updates what happens to the outputs when the controller loses tracking
+    UpdateFailsafe(FailsafeSettings),
+    /// Updates the controller button that starts and stops recording
+    UpdateRecording(RecordingSettings),
+    /// Starts recording every output tick to a new file
+    StartRecording,
+    /// Stops and closes the current recording
+    StopRecording,
+    /// Feeds a recording's frames through the stick processing in place of the controller. Must not be
+    /// empty.
+    StartReplay(Vec<Frame>),
+    /// Goes back to the live controller
+    StopReplay,
+    /// Updates replay speed, pausing, and whether it reaches the game
+    UpdateReplay(ReplaySettings),
+    /// Jumps to a point in the replay, in seconds
+    SeekReplay(f32),
 }
 
+/// Output rates offered to the user, in Hz
+pub const OUTPUT_RATES: [u32; 3] = [250, 500, 1000];
+pub const DEFAULT_OUTPUT_RATE: u32 = 250;
+
 pub struct VRSystemInformation {
     pub system_properties: SystemProperties,
 }
@@ -38,10 +89,569 @@ pub struct VRInputBounds {
     pub deadzone: f32,
     /// Remaps `0..=stick_max` to `0.0..=1.0` for gamepad output
     pub stick_max: f32,
+    /// Separate maximums for each direction, in place of `stick_max`
+    pub ranges: Option<StickRanges>,
+    /// Time constant the stick is smoothed with before the deadzone, in seconds, 0 for none
+    pub smoothing: f32,
 }
 
 impl Default for VRInputBounds {
-    fn default() -> Self { Self { deadzone: 0.1, stick_max: 0.85 } }
+    fn default() -> Self { Self { deadzone: 0.1, stick_max: 0.85, ranges: None, smoothing: 0.0 } }
+}
+
+impl VRInputBounds {
+    /// The maximum on each axis on the side `stick` is pushed towards.
+    pub fn max_for(&self, stick: [f32; 2]) -> [f32; 2] {
+        let max = match self.ranges {
+            Some(ranges) => [
+                if stick[0] < 0.0 { ranges.left } else { ranges.right },
+                if stick[1] < 0.0 { ranges.back } else { ranges.forward },
+            ],
+            None => [self.stick_max; 2],
+        };
+        max.map(|max| max.max(f32::EPSILON))
+    }
+
+    /// Why these bounds can't be used, if they can't: every maximum has to be past the deadzone,
+    /// and both within `0.0..=1.0`.
+    pub fn check(&self) -> Result<(), String> {
+        let maximums = match self.ranges {
+            Some(ranges) => vec![ranges.right, ranges.left, ranges.forward, ranges.back],
+            None => vec![self.stick_max],
+        };
+        if !(0.0..=1.0).contains(&self.deadzone) {
+            return Err(format!("deadzone needs to be between 0 and 1, got {}", self.deadzone));
+        }
+        if let Some(max) = maximums.iter().find(|max| !(0.0..=1.0).contains(*max)) {
+            return Err(format!("max needs to be between 0 and 1, got {max}"));
+        }
+        if let Some(max) = maximums.iter().find(|max| **max <= self.deadzone) {
+            return Err(format!("max needs to be past the deadzone of {}, got {max}", self.deadzone));
+        }
+        if !(0.0..).contains(&self.smoothing) {
+            return Err(format!("smoothing needs a number of seconds, got {}", self.smoothing));
+        }
+        Ok(())
+    }
+}
+
+/// How far the stick is pushed at full output in each direction, in the same units as
+/// [`VRInputBounds::stick_max`].
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct StickRanges {
+    pub right: f32,
+    pub left: f32,
+    pub forward: f32,
+    pub back: f32,
+}
+
+/// Which of the controller's poses drives the stick.
+#[derive(PartialEq, Copy, Clone)]
+pub enum PoseSource {
+    /// Points forward out of the tip of the controller
+    Aim,
+    /// Follows the hand holding the controller
+    Grip,
+}
+
+impl Display for PoseSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PoseSource::Aim => write!(f, "Aim"),
+            PoseSource::Grip => write!(f, "Grip"),
+        }
+    }
+}
+
+/// The space the controller's orientation is measured in.
+#[derive(PartialEq, Copy, Clone)]
+pub enum ReferenceSpace {
+    /// The room, as set up in the runtime
+    Stage,
+    /// The seated origin, moved by the runtime's recenter
+    Local,
+    /// The headset, so the stick follows where you're looking
+    View,
+}
+
+impl ReferenceSpace {
+    pub const ALL: [ReferenceSpace; 3] = [ReferenceSpace::Stage, ReferenceSpace::Local, ReferenceSpace::View];
+}
+
+impl Display for ReferenceSpace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReferenceSpace::Stage => write!(f, "Stage"),
+            ReferenceSpace::Local => write!(f, "Local"),
+            ReferenceSpace::View => write!(f, "View"),
+        }
+    }
+}
+
+/// What the stick's forward direction follows when the player turns around.
+#[derive(PartialEq, Copy, Clone)]
+pub enum YawReference {
+    /// Forward is fixed in the reference space
+    None,
+    /// Forward follows the headset's heading, smoothed
+    Head,
+    /// Forward is the headset's heading when the seat was captured
+    Seat,
+}
+
+impl YawReference {
+    pub const ALL: [YawReference; 3] = [YawReference::None, YawReference::Head, YawReference::Seat];
+}
+
+impl Display for YawReference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            YawReference::None => write!(f, "Reference Space"),
+            YawReference::Head => write!(f, "Head"),
+            YawReference::Seat => write!(f, "Seat"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct TrackingSettings {
+    pub pose: PoseSource,
+    pub space: ReferenceSpace,
+    pub yaw: YawReference,
+    /// Seconds for the head heading to catch up with a turn
+    pub yaw_smoothing: f32,
+}
+
+impl TrackingSettings {
+    /// Whether the frame the controller is measured in differs between `self` and `other`
+    pub fn frame_differs(&self, other: &TrackingSettings) -> bool {
+        self.pose != other.pose || self.space != other.space || self.yaw != other.yaw
+    }
+}
+
+impl Default for TrackingSettings {
+    fn default() -> Self {
+        Self { pose: PoseSource::Aim, space: ReferenceSpace::Stage, yaw: YawReference::None, yaw_smoothing: 0.5 }
+    }
+}
+
+/// A digital input on the controller that FlightWand functions can be assigned to.
+/// Inputs assigned to a function are no longer forwarded to the virtual gamepad.
+#[derive(PartialEq, Copy, Clone)]
+pub enum ControllerButton {
+    None,
+    TrackpadClick,
+    TrackpadUp,
+    TrackpadDown,
+    TrackpadLeft,
+    TrackpadRight,
+    Grip,
+    Menu,
+    Trigger,
+}
+
+impl ControllerButton {
+    pub const ALL: [ControllerButton; 9] = [
+        ControllerButton::None,
+        ControllerButton::TrackpadClick,
+        ControllerButton::TrackpadUp,
+        ControllerButton::TrackpadDown,
+        ControllerButton::TrackpadLeft,
+        ControllerButton::TrackpadRight,
+        ControllerButton::Grip,
+        ControllerButton::Menu,
+        ControllerButton::Trigger,
+    ];
+}
+
+/// Controller buttons forwarded to the virtual gamepad, and the gamepad button each becomes
+pub const GAMEPAD_BUTTONS: [(ControllerButton, KeyCode); 6] = [
+    (ControllerButton::TrackpadUp, KeyCode::BTN_NORTH),
+    (ControllerButton::TrackpadRight, KeyCode::BTN_EAST),
+    (ControllerButton::TrackpadLeft, KeyCode::BTN_WEST),
+    (ControllerButton::TrackpadDown, KeyCode::BTN_SOUTH),
+    (ControllerButton::Menu, KeyCode::BTN_START),
+    (ControllerButton::Grip, KeyCode::BTN_TR2),
+];
+
+impl Display for ControllerButton {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ControllerButton::None => write!(f, "None"),
+            ControllerButton::TrackpadClick => write!(f, "Trackpad Click"),
+            ControllerButton::TrackpadUp => write!(f, "Trackpad Up"),
+            ControllerButton::TrackpadDown => write!(f, "Trackpad Down"),
+            ControllerButton::TrackpadLeft => write!(f, "Trackpad Left"),
+            ControllerButton::TrackpadRight => write!(f, "Trackpad Right"),
+            ControllerButton::Grip => write!(f, "Grip"),
+            ControllerButton::Menu => write!(f, "Menu"),
+            ControllerButton::Trigger => write!(f, "Trigger"),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct TrimSettings {
+    /// How far a single press of a trim button moves the trim offset, in output units
+    pub step: f32,
+    /// Nudges the trim towards the top of the stick preview
+    pub up: ControllerButton,
+    /// Nudges the trim towards the bottom of the stick preview
+    pub down: ControllerButton,
+    /// Nudges the trim to the left
+    pub left: ControllerButton,
+    /// Nudges the trim to the right
+    pub right: ControllerButton,
+    /// Makes the current stick deflection the new center
+    pub force_trim: ControllerButton,
+    /// Returns the trim offset to zero
+    pub reset: ControllerButton,
+}
+
+impl TrimSettings {
+    /// Whether `button` is assigned to any trim function.
+    pub fn claims(&self, button: ControllerButton) -> bool {
+        button != ControllerButton::None
+            && [self.up, self.down, self.left, self.right, self.force_trim, self.reset].contains(&button)
+    }
+}
+
+impl Default for TrimSettings {
+    fn default() -> Self {
+        Self {
+            step: 0.02,
+            up: ControllerButton::None,
+            down: ControllerButton::None,
+            left: ControllerButton::None,
+            right: ControllerButton::None,
+            force_trim: ControllerButton::None,
+            reset: ControllerButton::None,
+        }
+    }
+}
+
+/// What the stick output does while the engage button isn't held.
+#[derive(PartialEq, Copy, Clone)]
+pub enum ReleaseBehavior {
+    /// Axes and buttons all keep their last value
+    Freeze,
+    /// Axes return to the trim center, buttons stay live
+    Recenter,
+    /// Axes keep their last value, buttons stay live
+    HoldLast,
+}
+
+impl Display for ReleaseBehavior {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReleaseBehavior::Freeze => write!(f, "Freeze"),
+            ReleaseBehavior::Recenter => write!(f, "Recenter"),
+            ReleaseBehavior::HoldLast => write!(f, "Hold Last"),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct EngageSettings {
+    /// Button that has to be held for the stick to be live. `None` keeps it live at all times.
+    pub button: ControllerButton,
+    pub release: ReleaseBehavior,
+    /// Captures the current orientation as neutral every time the stick is engaged
+    pub recapture_neutral: bool,
+}
+
+impl Default for EngageSettings {
+    fn default() -> Self {
+        Self { button: ControllerButton::None, release: ReleaseBehavior::Recenter, recapture_neutral: false }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct HapticPulse {
+    pub enabled: bool,
+    /// `0.0..=1.0`
+    pub amplitude: f32,
+    /// Milliseconds
+    pub duration: f32,
+    /// Hz, `0.0` lets the runtime decide
+    pub frequency: f32,
+}
+
+impl Default for HapticPulse {
+    fn default() -> Self { Self { enabled: true, amplitude: 0.5, duration: 20.0, frequency: 0.0 } }
+}
+
+/// Things that can make the controller vibrate.
+#[derive(PartialEq, Copy, Clone)]
+pub enum HapticEvent {
+    /// The stick left the deadzone
+    DeadzoneExit,
+    /// The stick reached full deflection
+    Saturation,
+    /// The trigger crossed the throttle detent
+    ThrottleDetent,
+    /// The trim offset changed
+    TrimStep,
+    /// A new neutral orientation was captured
+    Recenter,
+    /// The stick was engaged or released
+    ModeSwitch,
+}
+
+impl HapticEvent {
+    pub const ALL: [HapticEvent; 6] = [
+        HapticEvent::DeadzoneExit,
+        HapticEvent::Saturation,
+        HapticEvent::ThrottleDetent,
+        HapticEvent::TrimStep,
+        HapticEvent::Recenter,
+        HapticEvent::ModeSwitch,
+    ];
+}
+
+impl Display for HapticEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HapticEvent::DeadzoneExit => write!(f, "Deadzone exit"),
+            HapticEvent::Saturation => write!(f, "Saturation"),
+            HapticEvent::ThrottleDetent => write!(f, "Throttle detent"),
+            HapticEvent::TrimStep => write!(f, "Trim step"),
+            HapticEvent::Recenter => write!(f, "Recenter"),
+            HapticEvent::ModeSwitch => write!(f, "Mode switch"),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct HapticSettings {
+    /// One pulse per [`HapticEvent`], in the order of [`HapticEvent::ALL`]
+    pub pulses: [HapticPulse; 6],
+    /// Trigger position of the throttle detent, `0.0..=1.0`
+    pub throttle_detent: f32,
+}
+
+impl HapticSettings {
+    pub fn pulse(&self, event: HapticEvent) -> &HapticPulse { &self.pulses[event as usize] }
+
+    pub fn pulse_mut(&mut self, event: HapticEvent) -> &mut HapticPulse { &mut self.pulses[event as usize] }
+}
+
+impl Default for HapticSettings {
+    fn default() -> Self {
+        let mut pulses = [HapticPulse::default(); 6];
+        pulses[HapticEvent::Saturation as usize].amplitude = 0.3;
+        pulses[HapticEvent::Recenter as usize].duration = 100.0;
+        pulses[HapticEvent::ModeSwitch as usize].duration = 50.0;
+        Self { pulses, throttle_detent: 0.8 }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct ForceFeedbackSettings {
+    /// Forward rumble from games to the controller
+    pub enabled: bool,
+    /// Multiplies the strength of every effect
+    pub gain: f32,
+}
+
+impl Default for ForceFeedbackSettings {
+    fn default() -> Self { Self { enabled: true, gain: 1.0 } }
+}
+
+/// What the axes do while the controller isn't tracked. Buttons are always released.
+#[derive(PartialEq, Copy, Clone)]
+pub enum FailsafeBehavior {
+    /// Axes jump to the trim center
+    Center,
+    /// Axes keep their last tracked value
+    HoldLast,
+    /// Axes slide from their last tracked value to the trim center
+    Decay,
+}
+
+impl FailsafeBehavior {
+    pub const ALL: [FailsafeBehavior; 3] =
+        [FailsafeBehavior::Center, FailsafeBehavior::HoldLast, FailsafeBehavior::Decay];
+}
+
+impl Display for FailsafeBehavior {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FailsafeBehavior::Center => write!(f, "Center"),
+            FailsafeBehavior::HoldLast => write!(f, "Hold Last"),
+            FailsafeBehavior::Decay => write!(f, "Decay to Center"),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct FailsafeSettings {
+    pub behavior: FailsafeBehavior,
+    /// Milliseconds for `Decay` to reach the center
+    pub decay: f32,
+}
+
+impl Default for FailsafeSettings {
+    fn default() -> Self { Self { behavior: FailsafeBehavior::Decay, decay: 500.0 } }
+}
+
+#[derive(Clone, Copy)]
+pub struct RecordingSettings {
+    /// Starts and stops recording from the controller
+    pub button: ControllerButton,
+}
+
+impl Default for RecordingSettings {
+    fn default() -> Self { Self { button: ControllerButton::None } }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct ReplaySettings {
+    pub paused: bool,
+    /// `1.0` is real time
+    pub speed: f32,
+    /// Send the replayed output to the virtual gamepad and keyboard/mouse, instead of only the UI
+    pub drive_gamepad: bool,
+}
+
+impl Default for ReplaySettings {
+    fn default() -> Self { Self { paused: false, speed: 1.0, drive_gamepad: false } }
+}
+
+#[derive(Clone, Copy)]
+pub struct ReplayProgress {
+    /// Seconds
+    pub position: f32,
+    /// Seconds
+    pub length: f32,
+    pub finished: bool,
+}
+
+/// Treats the controller as the top of a stick that pivots around a point below it.
+#[derive(Clone, Copy, PartialEq)]
+pub struct GimbalSettings {
+    /// Use the controller's position around the pivot instead of only its orientation
+    pub enabled: bool,
+    /// Base of the virtual stick, in meters in the reference space
+    pub pivot: [f32; 3],
+    /// Distance from the pivot to the controller when the pivot is captured, in meters
+    pub length: f32,
+    /// How much the controller's orientation counts, `0.0` for position only, `1.0` for orientation only
+    pub orientation_blend: f32,
+}
+
+impl Default for GimbalSettings {
+    fn default() -> Self { Self { enabled: false, pivot: [0.0; 3], length: 0.3, orientation_blend: 0.0 } }
+}
+
+/// Up to four keys pressed together, written like `LEFTCTRL+W`.
+#[derive(Clone, Copy, PartialEq, Default)]
+pub struct KeyCombo(pub [Option<KeyCode>; 4]);
+
+impl KeyCombo {
+    pub fn keys(&self) -> impl Iterator<Item = KeyCode> + '_ { self.0.iter().flatten().copied() }
+}
+
+impl From<KeyCode> for KeyCombo {
+    fn from(key: KeyCode) -> Self { Self([Some(key), None, None, None]) }
+}
+
+impl FromStr for KeyCombo {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut combo = KeyCombo::default();
+        let names = s.split('+').map(str::trim).filter(|name| !name.is_empty());
+
+        for (i, name) in names.enumerate() {
+            if i >= combo.0.len() {
+                return Err(format!("At most {} keys can be combined", combo.0.len()));
+            }
+
+            let name = name.to_uppercase();
+            // `BTN_` is needed for mouse buttons, `KEY_` is implied
+            let full_name =
+                if name.starts_with("KEY_") || name.starts_with("BTN_") { name } else { format!("KEY_{name}") };
+            combo.0[i] = Some(KeyCode::from_str(&full_name).map_err(|_| format!("Unknown key \"{full_name}\""))?);
+        }
+
+        Ok(combo)
+    }
+}
+
+impl Display for KeyCombo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let names: Vec<String> =
+            self.keys().map(|key| format!("{:?}", key).trim_start_matches("KEY_").to_owned()).collect();
+        write!(f, "{}", names.join("+"))
+    }
+}
+
+/// How the keyboard and mouse output represents the stick.
+#[derive(PartialEq, Copy, Clone)]
+pub enum KeyboardMouseStick {
+    /// Relative mouse motion, faster the further the stick is deflected
+    Mouse,
+    /// Stick keys pulsed with a duty cycle proportional to deflection
+    Keys,
+}
+
+impl Display for KeyboardMouseStick {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeyboardMouseStick::Mouse => write!(f, "Mouse"),
+            KeyboardMouseStick::Keys => write!(f, "Keys"),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct KeyboardMouseSettings {
+    /// Creates a virtual keyboard/mouse and sends output to it
+    pub enabled: bool,
+    /// Stops sending output to the virtual joystick
+    pub replace_joystick: bool,
+    pub stick: KeyboardMouseStick,
+    /// Mouse speed at full deflection, in counts per second
+    pub sensitivity: f32,
+    /// Exponent applied to deflection before scaling, `1.0` is linear
+    pub acceleration: f32,
+    /// Moves the mouse down when the stick is pushed up
+    pub invert_y: bool,
+    /// Length of one key pulse cycle, in milliseconds
+    pub pulse_period: f32,
+    /// Keys for pushing the stick up, down, left and right
+    pub stick_keys: [KeyCombo; 4],
+    /// Keys for each controller button, in the order of [`ControllerButton::ALL`]
+    pub button_keys: [KeyCombo; 9],
+}
+
+impl KeyboardMouseSettings {
+    pub fn button_keys(&self, button: ControllerButton) -> &KeyCombo { &self.button_keys[button as usize] }
+
+    pub fn button_keys_mut(&mut self, button: ControllerButton) -> &mut KeyCombo {
+        &mut self.button_keys[button as usize]
+    }
+}
+
+impl Default for KeyboardMouseSettings {
+    fn default() -> Self {
+        let mut button_keys = [KeyCombo::default(); 9];
+        button_keys[ControllerButton::Trigger as usize] = KeyCode::BTN_LEFT.into();
+        button_keys[ControllerButton::Menu as usize] = KeyCode::KEY_ESC.into();
+
+        Self {
+            enabled: false,
+            replace_joystick: false,
+            stick: KeyboardMouseStick::Mouse,
+            sensitivity: 1000.0,
+            acceleration: 1.5,
+            invert_y: false,
+            pulse_period: 100.0,
+            stick_keys: [KeyCode::KEY_W.into(), KeyCode::KEY_S.into(), KeyCode::KEY_A.into(), KeyCode::KEY_D.into()],
+            button_keys,
+        }
+    }
 }
 
 pub enum VRSystemFailure {
@@ -90,6 +700,113 @@ pub enum VR2UI {
     Running(VRSystemInformation),
     /// Backend has failed. Application is no longer operational.
     Failure(VRSystemFailure),
-    /// Controller rotation update (for visualization)
-    RotationUpdate(Quaternionf),
+    /// The trim offset changed, in output units
+    TrimUpdate([f32; 2]),
+    /// The backend changed its knowledge of "up" (recentering)
+    IdentityUpdate([f32; 3]),
+    /// The stick bounds were changed from outside the frontend
+    BoundsUpdate(VRInputBounds),
+    /// The stick was engaged (`true`) or released (`false`)
+    EngageUpdate(bool),
+    /// The gimbal pivot was captured, in meters in the reference space
+    PivotUpdate([f32; 3]),
+    /// The controller's tracking was regained (`true`) or lost (`false`), and how many times it's been lost
+    TrackingUpdate(bool, u32),
+    /// Recording started to the given file (`Some`), or stopped (`None`)
+    RecordingUpdate(Option<PathBuf>),
+    /// A replay started (`Some`), or stopped (`None`). Progress after that comes as telemetry
+    ReplayUpdate(Option<ReplayProgress>),
+    /// The virtual gamepad was created, with its input device under /sys
+    GamepadDevice(PathBuf),
+}
+
+/// Readings sent every few milliseconds, through the telemetry queues rather than the message channel.
+#[derive(Clone, Copy)]
+pub enum VRTelemetry {
+    /// Controller rotation update, and its position when that's known (for visualization)
+    Rotation(Quaternionf, Option<[f32; 3]>),
+    /// Where the replay is
+    ReplayProgress(ReplayProgress),
+    /// What the virtual gamepad currently reports
+    Output(OutputState),
+    /// Raw controller input, with changes since the last reading folded in
+    Input(InputReadings),
+}
+
+/// One action's state, as of the last sync.
+#[derive(Clone, Copy, Default)]
+pub struct ActionReading<T> {
+    pub value: T,
+    /// Bound to something that's there
+    pub active: bool,
+    /// Changed since the sync before
+    pub changed: bool,
+}
+
+impl<T: openxr::ActionInput> From<openxr::ActionState<T>> for ActionReading<T> {
+    fn from(state: openxr::ActionState<T>) -> Self {
+        Self { value: state.current_state, active: state.is_active, changed: state.changed_since_last_sync }
+    }
+}
+
+/// Every bound action as the runtime reports it, for diagnosing bindings.
+#[derive(Clone, Copy, Default)]
+pub struct InputReadings {
+    pub pose_active: bool,
+    /// The pose's orientation is being tracked, not guessed
+    pub pose_tracked: bool,
+    pub trigger: ActionReading<f32>,
+    pub trackpad_x: ActionReading<f32>,
+    pub trackpad_y: ActionReading<f32>,
+    pub trackpad_touch: ActionReading<bool>,
+    pub trackpad_click: ActionReading<bool>,
+    pub grip: ActionReading<bool>,
+    pub menu: ActionReading<bool>,
+    /// Read back from a recording instead of the controller
+    pub replayed: bool,
+}
+
+impl InputReadings {
+    /// Takes the values from `newer`, remembering whether anything changed in either.
+    pub fn accumulate(&mut self, newer: InputReadings) {
+        let changed = [
+            self.trigger.changed,
+            self.trackpad_x.changed,
+            self.trackpad_y.changed,
+            self.trackpad_touch.changed,
+            self.trackpad_click.changed,
+            self.grip.changed,
+            self.menu.changed,
+        ];
+        *self = newer;
+        let readings = [
+            &mut self.trigger.changed,
+            &mut self.trackpad_x.changed,
+            &mut self.trackpad_y.changed,
+            &mut self.trackpad_touch.changed,
+            &mut self.trackpad_click.changed,
+            &mut self.grip.changed,
+            &mut self.menu.changed,
+        ];
+        for (reading, changed) in readings.into_iter().zip(changed) {
+            *reading |= changed;
+        }
+    }
+}
+
+/// The virtual gamepad's state, as last sent to it.
+#[derive(Clone, Copy, Default)]
+pub struct OutputState {
+    /// Stick `x, y` as emitted, with `i16::MAX` at full deflection
+    pub axes: [i32; 2],
+    /// Pressed, in the order of [`GAMEPAD_BUTTONS`]
+    pub buttons: [bool; GAMEPAD_BUTTONS.len()],
+    /// Whether the last tick reached the gamepad. Its buttons are released and its axes left alone
+    /// while the keyboard and mouse replace it, or a replay isn't sent to it
+    pub live: bool,
+}
+
+impl OutputState {
+    /// The stick as a fraction of full deflection.
+    pub fn stick(&self) -> [f32; 2] { self.axes.map(|v| v as f32 / i16::MAX as f32) }
 }
@@ -0,0 +1,386 @@
+//! Flight recordings.
+//!
+//! A recording (`.fwrec`) starts with the line `FWREC 2`, followed by one line of JSON describing
+//! the calibration at the time recording started. After that come frames, one per output tick,
+//! until the end of the file. All numbers are little-endian.
+//!
+//! | Bytes | Type                  | Field                                                  |
+//! |-------|-----------------------|--------------------------------------------------------|
+//! | 8     | `u64`                 | Nanoseconds since recording started                    |
+//! | 16    | `f32` × 4             | Raw controller orientation `x, y, z, w`                |
+//! | 12    | `f32` × 3             | Raw controller position in meters                      |
+//! | 4     | `f32`                 | Heading of the frame the stick is measured in, radians |
+//! | 1     | `u8`                  | Flags: 1 tracked, 2 trackpad click, 4 grip, 8 menu     |
+//! | 4     | `f32`                 | Trigger                                                |
+//! | 8     | `f32` × 2             | Trackpad `x, y`                                        |
+//! | 8     | `f32` × 2             | Computed stick axes `x, y`, `-1.0..=1.0`               |
+//! | 1     | `u8`                  | Number of events emitted to the virtual gamepad, `n`   |
+//! | 8 × n | (`u16`, `u16`, `i32`) | Each event's type, code and value                      |
+//!
+//! `FWREC 1` recordings are the same without the heading, which reads as zero.
+
+use evdev::{AbsoluteAxisCode, EventType, InputEvent, KeyCode};
+use serde_json::Value;
+use std::{
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+const MAGIC: &str = "FWREC 2";
+/// Recordings from before the heading was recorded
+const MAGIC_V1: &str = "FWREC 1";
+
+const TRACKED: u8 = 1;
+const TRACKPAD_CLICK: u8 = 2;
+const GRIP: u8 = 4;
+const MENU: u8 = 8;
+
+/// Everything that happened in one output tick.
+#[derive(Clone, Default)]
+pub struct Frame {
+    /// Since recording started
+    pub time: Duration,
+    pub orientation: [f32; 4],
+    pub position: [f32; 3],
+    /// Heading of the frame the controller is measured in, from the yaw reference
+    pub body_yaw: f32,
+    pub tracked: bool,
+    pub trigger: f32,
+    pub trackpad: [f32; 2],
+    pub trackpad_click: bool,
+    pub grip: bool,
+    pub menu: bool,
+    pub axes: [f32; 2],
+    /// Sent to the virtual gamepad this tick
+    pub events: Vec<InputEvent>,
+}
+
+impl Frame {
+    fn write(&self, out: &mut impl Write) -> io::Result<()> {
+        let mut flags = 0;
+        for (set, flag) in
+            [(self.tracked, TRACKED), (self.trackpad_click, TRACKPAD_CLICK), (self.grip, GRIP), (self.menu, MENU)]
+        {
+            if set {
+                flags |= flag;
+            }
+        }
+
+        out.write_all(&(self.time.as_nanos() as u64).to_le_bytes())?;
+        for v in self.orientation.iter().chain(&self.position).chain([&self.body_yaw]) {
+            out.write_all(&v.to_le_bytes())?;
+        }
+        out.write_all(&[flags])?;
+        for v in [self.trigger].iter().chain(&self.trackpad).chain(&self.axes) {
+            out.write_all(&v.to_le_bytes())?;
+        }
+
+        let events = &self.events[..self.events.len().min(u8::MAX as usize)];
+        out.write_all(&[events.len() as u8])?;
+        for event in events {
+            out.write_all(&event.event_type().0.to_le_bytes())?;
+            out.write_all(&event.code().to_le_bytes())?;
+            out.write_all(&event.value().to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Reads a frame, which only has a heading if `body_yaw` is set.
+    fn read(input: &mut impl Read, body_yaw: bool) -> io::Result<Frame> {
+        let mut time = [0; 8];
+        input.read_exact(&mut time)?;
+
+        let pose = read_f32s(input, if body_yaw { 8 } else { 7 })?;
+        let mut flags = [0; 1];
+        input.read_exact(&mut flags)?;
+        let analog = read_f32s(input, 5)?;
+
+        let mut count = [0; 1];
+        input.read_exact(&mut count)?;
+        let mut events = Vec::with_capacity(count[0] as usize);
+        for _ in 0..count[0] {
+            let mut bytes = [0; 8];
+            input.read_exact(&mut bytes)?;
+            let kind = u16::from_le_bytes([bytes[0], bytes[1]]);
+            let code = u16::from_le_bytes([bytes[2], bytes[3]]);
+            let value = i32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+            events.push(InputEvent::new(kind, code, value));
+        }
+
+        let flags = flags[0];
+        Ok(Frame {
+            time: Duration::from_nanos(u64::from_le_bytes(time)),
+            orientation: [pose[0], pose[1], pose[2], pose[3]],
+            position: [pose[4], pose[5], pose[6]],
+            body_yaw: pose.get(7).copied().unwrap_or_default(),
+            tracked: flags & TRACKED != 0,
+            trigger: analog[0],
+            trackpad: [analog[1], analog[2]],
+            trackpad_click: flags & TRACKPAD_CLICK != 0,
+            grip: flags & GRIP != 0,
+            menu: flags & MENU != 0,
+            axes: [analog[3], analog[4]],
+            events,
+        })
+    }
+}
+
+fn read_f32s(input: &mut impl Read, count: usize) -> io::Result<Vec<f32>> {
+    let mut values = Vec::with_capacity(count);
+    for _ in 0..count {
+        let mut bytes = [0; 4];
+        input.read_exact(&mut bytes)?;
+        values.push(f32::from_le_bytes(bytes));
+    }
+    Ok(values)
+}
+
+/// Where recordings go unless told otherwise.
+pub fn default_dir() -> Option<PathBuf> {
+    Some(crate::util::xdg_home("XDG_DATA_HOME", ".local/share")?.join("FlightWand/recordings"))
+}
+
+/// Writes frames to a new recording.
+pub struct Recorder {
+    out: BufWriter<File>,
+    started: Instant,
+    pub path: PathBuf,
+}
+
+impl Recorder {
+    /// Creates a recording in `dir`, named after the current time. `calibration` goes in the header.
+    pub fn create(dir: &Path, calibration: Value) -> io::Result<Recorder> {
+        fs::create_dir_all(dir)?;
+        let started = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        // a recording restarted within the same second gets a number instead of replacing the last one
+        let mut number = 1;
+        let (path, file) = loop {
+            let path = match number {
+                1 => dir.join(format!("flight-{started}.fwrec")),
+                number => dir.join(format!("flight-{started}-{number}.fwrec")),
+            };
+            match File::options().write(true).create_new(true).open(&path) {
+                Ok(file) => break (path, file),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => number += 1,
+                Err(err) => return Err(err),
+            }
+        };
+
+        let mut out = BufWriter::new(file);
+        let header = serde_json::json!({ "started": started, "calibration": calibration });
+        writeln!(out, "{MAGIC}")?;
+        writeln!(out, "{header}")?;
+
+        Ok(Recorder { out, started: Instant::now(), path })
+    }
+
+    /// Writes `frame`, stamping it with the time since recording started.
+    pub fn write(&mut self, mut frame: Frame) -> io::Result<()> {
+        frame.time = self.started.elapsed();
+        frame.write(&mut self.out)
+    }
+
+    pub fn finish(mut self) -> io::Result<()> { self.out.flush() }
+}
+
+/// Reads a whole recording, returning its header and frames.
+pub fn read(path: &Path) -> io::Result<(Value, Vec<Frame>)> {
+    let mut input = BufReader::new(File::open(path)?);
+
+    let mut line = String::new();
+    input.read_line(&mut line)?;
+    let body_yaw = match line.trim_end() {
+        MAGIC => true,
+        MAGIC_V1 => false,
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "not a FlightWand recording")),
+    };
+    line.clear();
+    input.read_line(&mut line)?;
+    let header = serde_json::from_str(&line).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+    let mut frames = Vec::new();
+    loop {
+        match Frame::read(&mut input, body_yaw) {
+            Ok(frame) => frames.push(frame),
+            // also drops a frame cut short by a crash
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(err) => return Err(err),
+        }
+    }
+    Ok((header, frames))
+}
+
+/// Names an event the way the kernel headers do, like `BTN_NORTH=1`.
+fn describe(event: &InputEvent) -> String {
+    match event.event_type() {
+        EventType::KEY => format!("{:?}={}", KeyCode(event.code()), event.value()),
+        EventType::ABSOLUTE => format!("{:?}={}", AbsoluteAxisCode(event.code()), event.value()),
+        kind => format!("{:?}:{}={}", kind, event.code(), event.value()),
+    }
+}
+
+/// Converts a recording to CSV next to it, returning the new file's path. The emitted events are
+/// written space separated in the last column.
+pub fn export_csv(path: &Path) -> io::Result<PathBuf> {
+    let (_, frames) = read(path)?;
+    let csv = path.with_extension("csv");
+    let mut out = BufWriter::new(File::create(&csv)?);
+
+    writeln!(
+        out,
+        "time,qx,qy,qz,qw,px,py,pz,body_yaw,tracked,trigger,trackpad_x,trackpad_y,trackpad_click,grip,menu,axis_x,axis_y,events"
+    )?;
+    for frame in frames {
+        let [qx, qy, qz, qw] = frame.orientation;
+        let [px, py, pz] = frame.position;
+        let events: Vec<String> = frame.events.iter().map(describe).collect();
+        writeln!(
+            out,
+            "{},{qx},{qy},{qz},{qw},{px},{py},{pz},{},{},{},{},{},{},{},{},{},{},{}",
+            frame.time.as_secs_f64(),
+            frame.body_yaw,
+            frame.tracked as u8,
+            frame.trigger,
+            frame.trackpad[0],
+            frame.trackpad[1],
+            frame.trackpad_click as u8,
+            frame.grip as u8,
+            frame.menu as u8,
+            frame.axes[0],
+            frame.axes[1],
+            events.join(" "),
+        )?;
+    }
+    out.flush()?;
+    Ok(csv)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty directory of its own for each test.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("flightwand-test-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn frame(step: u16) -> Frame {
+        let v = step as f32 / 10.0;
+        Frame {
+            time: Duration::ZERO,
+            orientation: [v, -v, 0.5, 0.75],
+            position: [v, 1.2, -v],
+            body_yaw: -v,
+            tracked: step.is_multiple_of(2),
+            trigger: v,
+            trackpad: [-0.5, v],
+            trackpad_click: step.is_multiple_of(3),
+            grip: true,
+            menu: false,
+            axes: [v, -1.0],
+            events: (0..step).map(|i| InputEvent::new(1, KeyCode::BTN_SOUTH.0, i as i32 % 2)).collect(),
+        }
+    }
+
+    fn record(dir: &Path, frames: &[Frame]) -> PathBuf {
+        let mut recorder = Recorder::create(dir, serde_json::json!({ "deadzone": 0.1 })).unwrap();
+        for frame in frames {
+            recorder.write(frame.clone()).unwrap();
+        }
+        let path = recorder.path.clone();
+        recorder.finish().unwrap();
+        path
+    }
+
+    #[test]
+    fn frames_round_trip() {
+        let dir = temp_dir("round-trip");
+        let written: Vec<Frame> = (0..4).map(frame).collect();
+        let path = record(&dir, &written);
+
+        let (header, read) = read(&path).unwrap();
+        assert_eq!(header["calibration"]["deadzone"], 0.1);
+        assert_eq!(read.len(), written.len());
+        for (read, written) in read.iter().zip(&written) {
+            assert_eq!(read.orientation, written.orientation);
+            assert_eq!(read.position, written.position);
+            assert_eq!(read.body_yaw, written.body_yaw);
+            assert_eq!(
+                (read.tracked, read.trackpad_click, read.grip, read.menu),
+                (written.tracked, written.trackpad_click, written.grip, written.menu)
+            );
+            assert_eq!((read.trigger, read.trackpad, read.axes), (written.trigger, written.trackpad, written.axes));
+            let events = |frame: &Frame| -> Vec<(u16, u16, i32)> {
+                frame.events.iter().map(|e| (e.event_type().0, e.code(), e.value())).collect()
+            };
+            assert_eq!(events(read), events(written));
+        }
+        assert!(read.windows(2).all(|pair| pair[0].time <= pair[1].time));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn recordings_in_the_same_second_get_their_own_files() {
+        let dir = temp_dir("same-second");
+        let first = record(&dir, &[frame(0)]);
+        let second = record(&dir, &[frame(1)]);
+        assert_ne!(first, second);
+        assert_eq!(read(&first).unwrap().1.len(), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn csv_has_a_header_and_a_row_per_frame() {
+        let dir = temp_dir("csv");
+        let path = record(&dir, &[frame(0), frame(2)]);
+        let csv = fs::read_to_string(export_csv(&path).unwrap()).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+
+        assert_eq!(lines.len(), 3);
+        let columns: Vec<&str> = lines[0].split(',').collect();
+        assert_eq!(columns[0], "time");
+        assert_eq!(columns.last(), Some(&"events"));
+        let row: Vec<&str> = lines[2].split(',').collect();
+        assert_eq!(row.len(), columns.len());
+        let column = |name: &str| row[columns.iter().position(|column| *column == name).unwrap()];
+        assert_eq!(column("body_yaw"), "-0.2");
+        assert_eq!(column("tracked"), "1");
+        assert_eq!(column("grip"), "1");
+        assert_eq!(column("axis_y"), "-1");
+        assert_eq!(column("events"), "BTN_SOUTH=0 BTN_SOUTH=1");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn truncated_frames_are_dropped() {
+        let dir = temp_dir("truncated");
+        let path = record(&dir, &[frame(0), frame(1)]);
+        let bytes = fs::read(&path).unwrap();
+        fs::write(&path, &bytes[..bytes.len() - 3]).unwrap();
+
+        let (_, frames) = read(&path).unwrap();
+        assert_eq!(frames.len(), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn bad_files_are_errors() {
+        let dir = temp_dir("bad");
+        let path = dir.join("bad.fwrec");
+
+        fs::write(&path, "FWREC 9\n{}\n").unwrap();
+        assert_eq!(read(&path).err().map(|err| err.kind()), Some(io::ErrorKind::InvalidData));
+        fs::write(&path, "").unwrap();
+        assert_eq!(read(&path).err().map(|err| err.kind()), Some(io::ErrorKind::InvalidData));
+        fs::write(&path, format!("{MAGIC}\nnot json\n")).unwrap();
+        assert_eq!(read(&path).err().map(|err| err.kind()), Some(io::ErrorKind::InvalidData));
+        assert!(read(&dir.join("missing.fwrec")).is_err());
+        assert!(export_csv(&path).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,111 @@
+//! High-rate readings from the backend, kept apart from the control messages in [`crate::pipe`].
+//!
+//! Every reader gets its own bounded queue. A reader that falls behind, like a minimized window,
+//! loses its oldest samples instead of making the queue grow, and the losses are counted.
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Condvar, Mutex, Weak},
+    time::Duration,
+};
+
+struct Ring<T> {
+    samples: VecDeque<T>,
+    capacity: usize,
+    dropped: u64,
+    /// The writer is gone, nothing more is coming
+    closed: bool,
+}
+
+struct Queue<T> {
+    ring: Mutex<Ring<T>>,
+    ready: Condvar,
+}
+
+/// How a reader is keeping up.
+#[derive(Clone, Copy, Default)]
+pub struct TelemetryStats {
+    /// Samples that were waiting at the last read
+    pub depth: usize,
+    pub capacity: usize,
+    /// Samples thrown away because the queue was full, in total
+    pub dropped: u64,
+}
+
+/// The writing end. Dropping it wakes every waiting reader.
+pub struct Telemetry<T> {
+    readers: Arc<Mutex<Vec<Weak<Queue<T>>>>>,
+}
+
+impl<T: Clone> Telemetry<T> {
+    pub fn new() -> Self { Self { readers: Arc::new(Mutex::new(Vec::new())) } }
+
+    /// Adds a reader that holds up to `capacity` samples.
+    pub fn subscribe(&self, capacity: usize) -> TelemetryReader<T> {
+        let queue = Arc::new(Queue {
+            ring: Mutex::new(Ring { samples: VecDeque::with_capacity(capacity), capacity, dropped: 0, closed: false }),
+            ready: Condvar::new(),
+        });
+        self.readers.lock().unwrap().push(Arc::downgrade(&queue));
+        TelemetryReader { queue, depth: 0 }
+    }
+
+    /// Hands `sample` to every reader, never waiting on them.
+    pub fn push(&self, sample: T) {
+        let mut readers = self.readers.lock().unwrap();
+        readers.retain(|reader| {
+            let Some(queue) = reader.upgrade() else { return false };
+            let mut ring = queue.ring.lock().unwrap();
+            if ring.samples.len() >= ring.capacity {
+                ring.samples.pop_front();
+                ring.dropped += 1;
+            }
+            ring.samples.push_back(sample.clone());
+            queue.ready.notify_one();
+            true
+        });
+    }
+}
+
+impl<T> Drop for Telemetry<T> {
+    fn drop(&mut self) {
+        for queue in self.readers.lock().unwrap().iter().filter_map(Weak::upgrade) {
+            queue.ring.lock().unwrap().closed = true;
+            queue.ready.notify_all();
+        }
+    }
+}
+
+/// The reading end.
+pub struct TelemetryReader<T> {
+    queue: Arc<Queue<T>>,
+    depth: usize,
+}
+
+impl<T> TelemetryReader<T> {
+    /// Takes every waiting sample, oldest first.
+    pub fn drain(&mut self) -> Vec<T> {
+        let samples: Vec<T> = self.queue.ring.lock().unwrap().samples.drain(..).collect();
+        self.depth = samples.len();
+        samples
+    }
+
+    /// Like [`drain`](Self::drain), but waits up to `timeout` for something to arrive. `None` once
+    /// the writer is gone and everything has been read.
+    pub fn wait(&mut self, timeout: Duration) -> Option<Vec<T>> {
+        let ring = self.queue.ring.lock().unwrap();
+        let (mut ring, _) =
+            self.queue.ready.wait_timeout_while(ring, timeout, |ring| ring.samples.is_empty() && !ring.closed).unwrap();
+        if ring.samples.is_empty() && ring.closed {
+            return None;
+        }
+        let samples: Vec<T> = ring.samples.drain(..).collect();
+        self.depth = samples.len();
+        Some(samples)
+    }
+
+    pub fn stats(&self) -> TelemetryStats {
+        let ring = self.queue.ring.lock().unwrap();
+        TelemetryStats { depth: self.depth, capacity: ring.capacity, dropped: ring.dropped }
+    }
+}
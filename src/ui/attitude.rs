@@ -0,0 +1,118 @@
+use crate::{mapping, pipe::VRInputBounds};
+use eframe::epaint::{Color32, Rounding, Stroke};
+use egui::{Align2, FontId, Pos2, Shape, Vec2, pos2, vec2};
+
+/// Pitch from the center to the top edge, in degrees
+const VISIBLE_PITCH: f32 = 40.0;
+/// Marks on the bank scale, in degrees either side of level
+const BANK_MARKS: [f32; 6] = [0.0, 10.0, 20.0, 30.0, 45.0, 60.0];
+
+const SKY: Color32 = Color32::from_rgb(40, 90, 160);
+const GROUND: Color32 = Color32::from_rgb(110, 70, 30);
+const DEADZONE: Color32 = Color32::from_rgb(0, 128, 200);
+
+/// Pitch and bank of `direction` relative to neutral, in radians. Leaning forward is pitching down,
+/// like pushing a stick.
+fn attitude(direction: &[f32; 4]) -> (f32, f32) {
+    let [x, y, z, _] = *direction;
+    (f32::atan2(z, y), f32::atan2(x, y))
+}
+
+/// Turns `offset`, with y going up, counter-clockwise by `angle` and puts it on screen around `center`.
+fn rotated(center: Pos2, angle: f32, offset: Vec2) -> Pos2 {
+    let (sin, cos) = angle.sin_cos();
+    center + vec2(offset.x * cos - offset.y * sin, -(offset.x * sin + offset.y * cos))
+}
+
+/// An artificial horizon for `direction`, with the bank scale and pitch ladder marking where the
+/// deadzone ends and the output saturates.
+#[profiling::function]
+pub fn attitude_indicator(direction: &[f32; 4], bounds: &VRInputBounds, ui: &mut egui::Ui) -> egui::Response {
+    let (rect, response) =
+        ui.allocate_exact_size(Vec2::splat(ui.spacing().interact_size.y * 10.0), egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    let center = rect.center();
+    let radius = rect.width() / 2.0;
+    let per_degree = radius / VISIBLE_PITCH;
+
+    let (pitch, bank) = attitude(direction);
+    // the horizon turns against the bank, and drops as the nose comes up
+    let horizon = |u: f32, v: f32| rotated(center, bank, vec2(u, v - pitch.to_degrees() * per_degree));
+
+    painter.rect_filled(rect, Rounding::ZERO, SKY);
+    let wide = radius * 3.0;
+    let deep = radius * 6.0;
+    painter.add(Shape::convex_polygon(
+        vec![horizon(-wide, 0.0), horizon(wide, 0.0), horizon(wide, -deep), horizon(-wide, -deep)],
+        GROUND,
+        Stroke::NONE,
+    ));
+    painter.line_segment([horizon(-wide, 0.0), horizon(wide, 0.0)], Stroke::new(1.5, Color32::WHITE));
+
+    // pitch ladder, long rungs every 10 degrees
+    for degrees in (-80i32..=80).step_by(5).filter(|d| *d != 0) {
+        let v = degrees as f32 * per_degree;
+        let half = if degrees % 10 == 0 { radius * 0.25 } else { radius * 0.12 };
+        painter.line_segment([horizon(-half, v), horizon(half, v)], Stroke::new(1.0, Color32::WHITE));
+        if degrees % 10 == 0 {
+            painter.text(
+                horizon(half + 10.0, v),
+                Align2::CENTER_CENTER,
+                degrees.abs().to_string(),
+                FontId::monospace(9.0),
+                Color32::WHITE,
+            );
+        }
+    }
+    // where each limit is reached leaning right, left, forward and back
+    let [right, forward] = bounds.max_for([1.0, 1.0]);
+    let [left, back] = bounds.max_for([-1.0, -1.0]);
+    let limits = [([bounds.deadzone; 4], DEADZONE), ([right, left, forward, back], Color32::GOLD)];
+    let limits = limits.map(|(deflections, color)| (deflections.map(|d| mapping::tilt_for(d).to_degrees()), color));
+    for ([_, _, forward, back], color) in limits {
+        // leaning forward is nose down
+        for v in [-forward, back] {
+            let v = v * per_degree;
+            painter.line_segment([horizon(-radius * 0.45, v), horizon(radius * 0.45, v)], Stroke::new(1.5, color));
+        }
+    }
+
+    // bank scale, fixed to the frame, with a pointer that turns with the horizon
+    let arc = radius * 0.85;
+    let bank_mark = |degrees: f32, length: f32, stroke: Stroke| {
+        let angle = degrees.to_radians();
+        let outward = vec2(-angle.sin(), -angle.cos());
+        painter.line_segment([center + outward * arc, center + outward * (arc + length)], stroke);
+    };
+    for degrees in BANK_MARKS {
+        let length = if degrees % 30.0 == 0.0 { 10.0 } else { 6.0 };
+        bank_mark(degrees, length, Stroke::new(1.0, Color32::WHITE));
+        bank_mark(-degrees, length, Stroke::new(1.0, Color32::WHITE));
+    }
+    for ([right, left, _, _], color) in limits {
+        // the pointer swings left when banking right
+        bank_mark(right, 10.0, Stroke::new(2.0, color));
+        bank_mark(-left, 10.0, Stroke::new(2.0, color));
+    }
+    let pointer = [vec2(0.0, arc), vec2(-5.0, arc - 8.0), vec2(5.0, arc - 8.0)].map(|p| rotated(center, bank, p));
+    painter.add(Shape::convex_polygon(pointer.to_vec(), Color32::WHITE, Stroke::NONE));
+
+    // the wand itself, fixed in the middle
+    let wing = Stroke::new(3.0, Color32::GOLD);
+    painter.line_segment([center - vec2(radius * 0.4, 0.0), center - vec2(radius * 0.12, 0.0)], wing);
+    painter.line_segment([center + vec2(radius * 0.12, 0.0), center + vec2(radius * 0.4, 0.0)], wing);
+    painter.line_segment([center - vec2(radius * 0.12, 0.0), center + vec2(0.0, radius * 0.06)], wing);
+    painter.line_segment([center + vec2(0.0, radius * 0.06), center + vec2(radius * 0.12, 0.0)], wing);
+    painter.circle_filled(center, 2.0, Color32::GOLD);
+
+    painter.text(
+        pos2(rect.min.x + 4.0, rect.max.y - 4.0),
+        Align2::LEFT_BOTTOM,
+        format!("P {:+4.0}°\nB {:+4.0}°", pitch.to_degrees(), bank.to_degrees()),
+        FontId::monospace(10.0),
+        Color32::WHITE,
+    );
+    painter.rect_stroke(rect, Rounding::ZERO, ui.visuals().noninteractive().bg_stroke);
+
+    response
+}
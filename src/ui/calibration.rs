@@ -0,0 +1,380 @@
+//! Step-by-step calibration of neutral and how far the stick goes in each direction.
+
+use crate::{
+    mapping::{Pose, StickPipeline},
+    pipe::{StickRanges, VRInputBounds},
+    util,
+};
+use eframe::epaint::{Color32, Stroke};
+use egui::{Sense, Shape, Vec2, vec2};
+use nalgebra::{Quaternion, UnitQuaternion, Vector3};
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// How long the controller has to be held still for a step to be captured on its own
+const HOLD_TIME: Duration = Duration::from_millis(1200);
+/// How far the controller can wander while counting as still, in degrees
+const STILL_ANGLE: f32 = 1.5;
+/// How far a direction has to be pushed before holding still captures it
+const MIN_RANGE: f32 = 0.15;
+/// How far a twist has to go before holding still captures it, in degrees
+const MIN_TWIST: f32 = 15.0;
+/// How far past the deadzone each direction should reach to be usable
+const DEADZONE_MARGIN: f32 = 0.05;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Step {
+    Neutral,
+    Forward,
+    Back,
+    Left,
+    Right,
+    TwistLeft,
+    TwistRight,
+}
+
+const STEPS: [Step; 7] =
+    [Step::Neutral, Step::Forward, Step::Back, Step::Left, Step::Right, Step::TwistLeft, Step::TwistRight];
+
+impl Step {
+    fn instruction(&self) -> &'static str {
+        match self {
+            Step::Neutral => "Hold the stick where it should rest",
+            Step::Forward => "Push the stick fully forward",
+            Step::Back => "Pull the stick fully back",
+            Step::Left => "Push the stick fully left",
+            Step::Right => "Push the stick fully right",
+            Step::TwistLeft => "Back to neutral, then twist the stick left",
+            Step::TwistRight => "Twist the stick right",
+        }
+    }
+}
+
+/// What the wizard found, before it's applied.
+pub struct Calibrated {
+    /// Neutral, in the form of [`crate::pipe::UI2VR::UpdateIdentity`]
+    pub up: [f32; 3],
+    pub ranges: StickRanges,
+    /// How far each twist went, in degrees
+    twist: [f32; 2],
+    /// Most the stick moved while twisting, as a fraction of full output
+    drift: f32,
+    problems: Vec<String>,
+}
+
+/// What the user chose in the wizard this frame.
+pub enum Outcome {
+    Continue,
+    Apply(Calibrated),
+    Cancel,
+}
+
+/// A controller pose from the telemetry.
+#[derive(Clone, Copy)]
+struct Reading {
+    orientation: UnitQuaternion<f32>,
+    /// In meters, `None` while the position isn't known
+    position: Option<[f32; 3]>,
+}
+
+pub struct Calibration {
+    /// Readings from a while longer than `HOLD_TIME`
+    window: VecDeque<(Instant, Reading)>,
+    /// One reading for each step done so far
+    captured: Vec<Reading>,
+    /// Capture a step once it's been held still, instead of only on the button
+    hold_to_capture: bool,
+    /// Worked out once every step is captured
+    result: Option<Calibrated>,
+}
+
+fn quaternion(orientation: &[f32; 4]) -> UnitQuaternion<f32> {
+    UnitQuaternion::from_quaternion(Quaternion::new(orientation[3], orientation[0], orientation[1], orientation[2]))
+}
+
+fn array(orientation: &UnitQuaternion<f32>) -> [f32; 4] {
+    let q = orientation.quaternion();
+    [q.i, q.j, q.k, q.w]
+}
+
+/// Rough mean of orientations that are all close together.
+fn mean_orientation(orientations: impl Iterator<Item = UnitQuaternion<f32>>) -> Option<UnitQuaternion<f32>> {
+    let mut orientations = orientations.peekable();
+    let first = *orientations.peek()?;
+    let sum = orientations.fold(Quaternion::new(0.0, 0.0, 0.0, 0.0), |sum, q| {
+        // q and -q are the same orientation, keep them on one side
+        if q.coords.dot(&first.coords) < 0.0 { sum - q.into_inner() } else { sum + q.into_inner() }
+    });
+    UnitQuaternion::try_new(sum, f32::EPSILON)
+}
+
+/// Mean of the readings, with the position left out if any of them don't have one.
+fn mean(readings: &[Reading]) -> Option<Reading> {
+    let orientation = mean_orientation(readings.iter().map(|reading| reading.orientation))?;
+    let positions: Option<Vec<[f32; 3]>> = readings.iter().map(|reading| reading.position).collect();
+    let position = positions.map(|positions| {
+        let sum = positions.iter().fold([0.0; 3], |sum, p| [sum[0] + p[0], sum[1] + p[1], sum[2] + p[2]]);
+        sum.map(|v| v / positions.len() as f32)
+    });
+    Some(Reading { orientation, position })
+}
+
+/// The stick, before the deadzone and scaling, for `reading` through `pipeline` with `up` as neutral.
+fn deflection(pipeline: &StickPipeline, up: [f32; 3], reading: &Reading) -> [f32; 2] {
+    let pose = Pose { orientation: array(&reading.orientation), position: reading.position };
+    StickPipeline { up, ..*pipeline }.deflection(&pose)
+}
+
+/// How far `orientation` is twisted around `up` from `neutral`, in degrees, positive to the left.
+fn twist(neutral: &UnitQuaternion<f32>, orientation: &UnitQuaternion<f32>, up: [f32; 3]) -> f32 {
+    // in the controller's own frame, where `up` is fixed
+    let relative = neutral.inverse() * orientation;
+    let along = relative.quaternion().imag().dot(&Vector3::from(up));
+    let angle = 2.0 * along.atan2(relative.quaternion().w);
+    // the long way round is the short way the other way
+    let angle = if angle > std::f32::consts::PI { angle - std::f32::consts::TAU } else { angle };
+    let angle = if angle < -std::f32::consts::PI { angle + std::f32::consts::TAU } else { angle };
+    angle.to_degrees()
+}
+
+impl Calibrated {
+    fn new(captured: &[Reading], pipeline: &StickPipeline) -> Self {
+        let bounds = &pipeline.bounds;
+        let neutral = captured[0].orientation;
+        let up = util::capture_up(&array(&neutral));
+        let at = |step: Step| {
+            let index = STEPS.iter().position(|s| *s == step).unwrap();
+            deflection(pipeline, up, &captured[index])
+        };
+        let ranges = StickRanges {
+            right: at(Step::Right)[0],
+            left: -at(Step::Left)[0],
+            forward: at(Step::Forward)[1],
+            back: -at(Step::Back)[1],
+        };
+
+        let twists = [Step::TwistLeft, Step::TwistRight].map(|step| {
+            let index = STEPS.iter().position(|s| *s == step).unwrap();
+            captured[index]
+        });
+        let twist = twists.map(|reading| self::twist(&neutral, &reading.orientation, up));
+        let candidate = VRInputBounds { ranges: Some(ranges), ..*bounds };
+        let drift = twists
+            .map(|reading| {
+                let [x, y] = deflection(pipeline, up, &reading);
+                let [max_x, max_y] = candidate.max_for([x, y]);
+                f32::hypot(x / max_x, y / max_y)
+            })
+            .into_iter()
+            .fold(0.0, f32::max);
+
+        let mut problems = Vec::new();
+        let named =
+            [("Right", ranges.right), ("Left", ranges.left), ("Forward", ranges.forward), ("Back", ranges.back)];
+        for (name, range) in named {
+            if range < bounds.deadzone + DEADZONE_MARGIN {
+                problems.push(format!(
+                    "{name} only reaches {range:.2}, which is too close to the deadzone of {:.2}",
+                    bounds.deadzone
+                ));
+            }
+        }
+        if twist[0] <= 0.0 || twist[1] >= 0.0 {
+            problems.push("The twists went the wrong way, or not at all".to_owned());
+        }
+
+        Self { up, ranges, twist, drift, problems }
+    }
+}
+
+impl Calibration {
+    pub fn new() -> Self { Self { window: VecDeque::new(), captured: Vec::new(), hold_to_capture: true, result: None } }
+
+    fn step(&self) -> Option<Step> { STEPS.get(self.captured.len()).copied() }
+
+    /// Takes the latest controller pose, from the rotation telemetry.
+    pub fn feed(&mut self, pose: &Pose, pipeline: &StickPipeline) {
+        let now = Instant::now();
+        self.window.push_back((now, Reading { orientation: quaternion(&pose.orientation), position: pose.position }));
+        while self.window.front().is_some_and(|(time, _)| now.duration_since(*time) > HOLD_TIME * 2) {
+            self.window.pop_front();
+        }
+        if self.hold_to_capture && self.held() >= 1.0 && self.far_enough(pipeline) {
+            self.capture(pipeline);
+        }
+    }
+
+    /// Latest reading
+    fn latest(&self) -> Option<Reading> { self.window.back().map(|(_, reading)| *reading) }
+
+    /// How much of `HOLD_TIME` the controller has been still for, 1 once it's long enough.
+    fn held(&self) -> f32 {
+        let Some(&(newest, latest)) = self.window.back() else { return 0.0 };
+        let still = self
+            .window
+            .iter()
+            .rev()
+            .take_while(|(_, reading)| reading.orientation.angle_to(&latest.orientation).to_degrees() < STILL_ANGLE)
+            .last();
+        let since = still.map_or(newest, |(time, _)| *time);
+        newest.duration_since(since).as_secs_f32() / HOLD_TIME.as_secs_f32()
+    }
+
+    /// Whether the latest reading is far enough along what the current step asks for.
+    fn far_enough(&self, pipeline: &StickPipeline) -> bool {
+        let (Some(step), Some(latest)) = (self.step(), self.latest()) else { return false };
+        let Some(neutral) = self.captured.first().map(|reading| reading.orientation) else {
+            return step == Step::Neutral;
+        };
+        let up = util::capture_up(&array(&neutral));
+        let [x, y] = deflection(pipeline, up, &latest);
+        match step {
+            Step::Neutral => true,
+            Step::Forward => y > MIN_RANGE,
+            Step::Back => y < -MIN_RANGE,
+            Step::Left => x < -MIN_RANGE,
+            Step::Right => x > MIN_RANGE,
+            Step::TwistLeft => twist(&neutral, &latest.orientation, up) > MIN_TWIST,
+            Step::TwistRight => twist(&neutral, &latest.orientation, up) < -MIN_TWIST,
+        }
+    }
+
+    /// Takes the current step from the still part of the window.
+    fn capture(&mut self, pipeline: &StickPipeline) {
+        let Some(latest) = self.latest() else { return };
+        let still: Vec<Reading> = self
+            .window
+            .iter()
+            .map(|(_, reading)| *reading)
+            .filter(|reading| reading.orientation.angle_to(&latest.orientation).to_degrees() < STILL_ANGLE)
+            .collect();
+        self.captured.push(mean(&still).unwrap_or(latest));
+        self.window.clear();
+        if self.captured.len() == STEPS.len() {
+            self.result = Some(Calibrated::new(&self.captured, pipeline));
+        }
+    }
+
+    /// Shows the current step, or the result once everything is captured. `pipeline` is the stick
+    /// processing as it is now.
+    pub fn show(&mut self, pipeline: &StickPipeline, ui: &mut egui::Ui) -> Outcome {
+        match self.step() {
+            Some(step) => self.show_step(step, pipeline, ui),
+            None => self.show_result(&pipeline.bounds, pipeline, ui),
+        }
+    }
+
+    fn show_step(&mut self, step: Step, pipeline: &StickPipeline, ui: &mut egui::Ui) -> Outcome {
+        ui.label(format!("Step {} of {}", self.captured.len() + 1, STEPS.len()));
+        ui.heading(step.instruction());
+        if self.latest().is_none() {
+            ui.weak("Waiting for the controller to be tracked");
+        }
+
+        let ready = self.far_enough(pipeline);
+        let held = if ready { self.held().min(1.0) } else { 0.0 };
+        ui.add(egui::ProgressBar::new(held).text(if ready { "Hold still" } else { "Not there yet" }));
+        ui.checkbox(&mut self.hold_to_capture, "Capture after holding still");
+
+        let mut outcome = Outcome::Continue;
+        ui.horizontal(|ui| {
+            if ui.add_enabled(self.latest().is_some(), egui::Button::new("Capture")).clicked() {
+                self.capture(pipeline);
+            }
+            if ui.add_enabled(!self.captured.is_empty(), egui::Button::new("Previous step")).clicked() {
+                self.captured.pop();
+                self.window.clear();
+            }
+            if ui.button("Cancel").clicked() {
+                outcome = Outcome::Cancel;
+            }
+        });
+        outcome
+    }
+
+    fn show_result(&mut self, bounds: &VRInputBounds, pipeline: &StickPipeline, ui: &mut egui::Ui) -> Outcome {
+        let Some(result) = &self.result else { return Outcome::Cancel };
+        let current = |stick: [f32; 2]| {
+            let [x, y] = bounds.max_for(stick);
+            x.max(y)
+        };
+
+        ui.heading("Preview");
+        egui::Grid::new("CalibrationResult").num_columns(3).show(ui, |ui| {
+            ui.label("");
+            ui.strong("Now");
+            ui.strong("Calibrated");
+            ui.end_row();
+            let named = [
+                ("Right", current([1.0, 0.0]), result.ranges.right),
+                ("Left", current([-1.0, 0.0]), result.ranges.left),
+                ("Forward", current([0.0, 1.0]), result.ranges.forward),
+                ("Back", current([0.0, -1.0]), result.ranges.back),
+            ];
+            for (name, now, calibrated) in named {
+                ui.label(name);
+                ui.monospace(format!("{now:.3}"));
+                ui.monospace(format!("{calibrated:.3}"));
+                ui.end_row();
+            }
+            ui.label("Twist");
+            ui.label("");
+            ui.monospace(format!("{:+.0}° {:+.0}°", result.twist[0], result.twist[1]))
+                .on_hover_text("Twisting isn't sent to the game, it's measured to check it doesn't move the stick");
+            ui.end_row();
+            ui.label("Drift while twisting");
+            ui.label("");
+            ui.monospace(format!("{:.0}%", result.drift * 100.0));
+            ui.end_row();
+        });
+        for problem in &result.problems {
+            ui.colored_label(Color32::RED, problem);
+        }
+
+        ui.label("Move the controller to try it out:");
+        let candidate = VRInputBounds { ranges: Some(result.ranges), ..*bounds };
+        let pipeline = StickPipeline { up: result.up, bounds: candidate, gimbal: pipeline.gimbal };
+        let stick = self.latest().map(|reading| {
+            let pose = Pose { orientation: array(&reading.orientation), position: reading.position };
+            pipeline.map(&pose, &Default::default(), |_| false).stick
+        });
+        preview(stick, &candidate, ui);
+
+        let (mut apply, mut restart, mut cancel) = (false, false, false);
+        ui.horizontal(|ui| {
+            apply = ui.add_enabled(result.problems.is_empty(), egui::Button::new("Apply")).clicked();
+            restart = ui.button("Start over").clicked();
+            cancel = ui.button("Cancel").clicked();
+        });
+        if restart {
+            *self = Self::new();
+        }
+        match self.result.take() {
+            Some(result) if apply => Outcome::Apply(result),
+            _ if cancel => Outcome::Cancel,
+            result => {
+                self.result = result;
+                Outcome::Continue
+            }
+        }
+    }
+}
+
+/// The candidate ranges as a ring, with where the controller would put the stick.
+fn preview(stick: Option<[f32; 2]>, bounds: &VRInputBounds, ui: &mut egui::Ui) {
+    let (rect, _) = ui.allocate_exact_size(Vec2::splat(ui.spacing().interact_size.y * 6.0), Sense::hover());
+    let painter = ui.painter_at(rect);
+    let stroke = ui.visuals().noninteractive().bg_stroke;
+    painter.circle(rect.center(), rect.width() / 2.0, Color32::BLACK, stroke);
+    let plot = |stick: [f32; 2]| {
+        let [max_x, max_y] = bounds.max_for(stick);
+        rect.center() + vec2(stick[0] * max_x, -stick[1] * max_y) * (rect.width() / 2.0)
+    };
+    let ring = (0..64).map(|i| i as f32 / 64.0 * std::f32::consts::TAU).map(|a| plot([a.cos(), a.sin()]));
+    painter.add(Shape::closed_line(ring.collect(), Stroke::new(1.0, Color32::GOLD)));
+    if let Some(stick) = stick {
+        painter.circle_filled(plot(stick.map(|v| v.clamp(-1.0, 1.0))), 4.0, Color32::WHITE);
+    }
+}
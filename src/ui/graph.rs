@@ -4,6 +4,7 @@ use egui::{FontId, Painter, Rect, pos2, vec2};
 const NUM_WIDTH: f32 = 50.0;
 
 #[profiling::function]
+#[allow(clippy::too_many_arguments)]
 fn draw_line(
     left: f32,
     right: f32,
@@ -1,48 +1,188 @@
+use crate::pipe::VRInputBounds;
 use eframe::egui_glow;
-use egui::{Align2, Color32, FontId, Rounding, mutex::Mutex};
+use egui::{Color32, Pos2, Rect, Rgba, Rounding, Stroke, mutex::Mutex, vec2};
 use egui_glow::glow;
-use nalgebra_glm::TVec3;
-use std::sync::Arc;
+use nalgebra_glm::{TVec3, Vec3};
+use std::{f32::consts::PI, sync::Arc};
+
+/// Camera yaw and pitch the view starts at and resets to
+const DEFAULT_ANGLE: (f32, f32) = (0.7, 0.3);
+const DEFAULT_DISTANCE: f32 = 4.0;
+const DISTANCE_RANGE: std::ops::RangeInclusive<f32> = 2.0..=10.0;
+/// Keeps the camera off the poles, where looking at the center with a vertical up vector breaks down
+const MAX_PITCH: f32 = 1.5;
+
+/// Named camera positions, as yaw and pitch.
+const PRESETS: [(&str, (f32, f32)); 4] =
+    [("Front", (PI / 2.0, 0.0)), ("Side", (0.0, 0.0)), ("Top", (PI / 2.0, MAX_PITCH)), ("3/4", DEFAULT_ANGLE)];
+
+// Wireframe stick dimensions, with the shaft one unit long so it meets the rotation trail
+const BASE_HALF_WIDTH: f32 = 0.35;
+const BASE_TOP: f32 = -0.1;
+const BASE_BOTTOM: f32 = -0.25;
+const GIMBAL_RADIUS: f32 = 0.12;
+const GRIP_START: f32 = 0.55;
+const GRIP_HALF_WIDTH: f32 = 0.07;
+/// Segments in each ring
+const RING_SEGMENTS: usize = 24;
+
+const STICK_COLOR: [f32; 4] = [0.8, 0.8, 0.8, 1.0];
+const NEUTRAL_COLOR: [f32; 4] = [0.5, 0.5, 0.5, 1.0];
+const DEADZONE_COLOR: [f32; 4] = [0.0, 0.5, 0.78, 1.0];
+const SATURATION_COLOR: [f32; 4] = [1.0, 0.84, 0.0, 1.0];
+
+/// What the wireframe stick shows.
+#[derive(Clone, Copy)]
+pub struct StickModel {
+    /// Output as a fraction of full deflection, like [`crate::pipe::OutputState::stick`]
+    pub stick: [f32; 2],
+    pub bounds: VRInputBounds,
+}
 
 pub struct Graph3D {
     gfx: Option<Arc<Mutex<GFX>>>,
     angle: (f32, f32),
+    distance: f32,
 }
 
+#[allow(clippy::upper_case_acronyms)]
 struct GFX {
     program: glow::Program,
     vertex_array: glow::VertexArray,
     vertex_buffer: glow::Buffer,
 }
 
-const VERTEX_SHADER_SOURCE: &str = r#"
-    const vec4 colors[3] = vec4[3](
-        vec4(1.0, 0.0, 0.0, 1.0),
-        vec4(0.0, 1.0, 0.0, 1.0),
-        vec4(0.0, 0.0, 1.0, 1.0)
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct Vertex {
+    position: [f32; 3],
+    color: [f32; 4],
+}
+
+/// Everything drawn in one frame.
+struct Scene {
+    /// Pairs of vertices
+    lines: Vec<Vertex>,
+    /// Recent directions, oldest first, drawn as a strip with a point on the newest
+    trail: Vec<Vertex>,
+}
+
+impl Scene {
+    fn line(&mut self, from: Vec3, to: Vec3, color: [f32; 4]) {
+        self.lines.push(Vertex { position: from.into(), color });
+        self.lines.push(Vertex { position: to.into(), color });
+    }
+
+    /// A circle around `axis` through `center`.
+    fn ring(&mut self, center: Vec3, axis: Vec3, radius: f32, color: [f32; 4]) {
+        let (u, v) = perpendiculars(&axis);
+        let point = |i: usize| {
+            let angle = i as f32 / RING_SEGMENTS as f32 * 2.0 * PI;
+            center + (u * angle.cos() + v * angle.sin()) * radius
+        };
+        for i in 0..RING_SEGMENTS {
+            self.line(point(i), point(i + 1), color);
+        }
+    }
+
+    /// A cone from the origin around the vertical through the stick directions `edge` gives, each
+    /// a stick deflection pointing `around` radians from the right.
+    fn cone(&mut self, edge: impl Fn(f32) -> [f32; 2], color: [f32; 4]) {
+        let point = |i: usize| leaning(edge(i as f32 / RING_SEGMENTS as f32 * 2.0 * PI));
+        for i in 0..RING_SEGMENTS {
+            self.line(point(i), point(i + 1), color);
+        }
+        for i in (0..RING_SEGMENTS).step_by(RING_SEGMENTS / 4) {
+            self.line(Vec3::zeros(), point(i), color);
+        }
+    }
+
+    /// A box with its bottom face centered on `from`, reaching `length` along `axis`.
+    fn prism(&mut self, from: Vec3, axis: Vec3, length: f32, half_width: f32, color: [f32; 4]) {
+        let (u, v) = perpendiculars(&axis);
+        let corners = [u + v, u - v, -u - v, -u + v].map(|offset| offset * half_width);
+        for i in 0..4 {
+            let (a, b) = (corners[i], corners[(i + 1) % 4]);
+            let top = axis * length;
+            self.line(from + a, from + b, color);
+            self.line(from + top + a, from + top + b, color);
+            self.line(from + a, from + top + a, color);
+        }
+    }
+}
+
+/// Two unit vectors perpendicular to `axis` and each other.
+fn perpendiculars(axis: &Vec3) -> (Vec3, Vec3) {
+    let axis = axis.normalize();
+    let other = if axis.x.abs() < 0.9 { Vec3::x() } else { Vec3::z() };
+    let u = axis.cross(&other).normalize();
+    (u, axis.cross(&u))
+}
+
+/// Direction the stick points at `deflection`, before the deadzone and scaling, undoing
+/// [`crate::mapping::rot_to_joy`].
+fn leaning(deflection: [f32; 2]) -> Vec3 {
+    let [x, y] = deflection.map(|v| v.clamp(-1.0, 1.0).asin());
+    // stick x follows the direction's x, stick y its negative z
+    let horizontal = Vec3::new(x, 0.0, -y);
+    let length = horizontal.norm();
+    if length >= 1.0 {
+        return horizontal / length;
+    }
+    horizontal + Vec3::y() * f32::sqrt(1.0 - length * length)
+}
+
+fn scene(buffer: &[[f32; 4]; 100], model: &StickModel) -> Scene {
+    let mut scene = Scene { lines: Vec::new(), trail: Vec::new() };
+
+    // axes, dimmer on the negative side
+    for (axis, color) in [(Vec3::x(), [1.0, 0.0, 0.0, 1.0]), (Vec3::z(), [0.0, 0.0, 1.0, 1.0])] {
+        scene.lines.push(Vertex { position: axis.into(), color });
+        scene.lines.push(Vertex { position: (-axis).into(), color: color.map(|c| c * 0.5) });
+    }
+
+    scene.line(Vec3::zeros(), Vec3::y() * 1.2, NEUTRAL_COLOR);
+    let bounds = model.bounds;
+    scene.cone(|around| [around.cos(), around.sin()].map(|v| v * bounds.deadzone), DEADZONE_COLOR);
+    scene.cone(
+        |around| {
+            let edge = [around.cos(), around.sin()];
+            let max = bounds.max_for(edge);
+            [edge[0] * max[0], edge[1] * max[1]]
+        },
+        SATURATION_COLOR,
     );
-    in vec4 aPos;
+
+    // base plate and the fixed half of the gimbal
+    scene.prism(Vec3::y() * BASE_BOTTOM, Vec3::y(), BASE_TOP - BASE_BOTTOM, BASE_HALF_WIDTH, STICK_COLOR);
+    scene.ring(Vec3::zeros(), Vec3::x(), GIMBAL_RADIUS, STICK_COLOR);
+
+    // the moving half, the shaft and the grip
+    let stick = model.stick.map(|v| v.clamp(-1.0, 1.0));
+    let max = model.bounds.max_for(stick);
+    let direction = leaning([stick[0] * max[0], stick[1] * max[1]]);
+    scene.ring(Vec3::zeros(), direction, GIMBAL_RADIUS * 0.8, STICK_COLOR);
+    scene.line(Vec3::zeros(), direction * GRIP_START, STICK_COLOR);
+    scene.prism(direction * GRIP_START, direction, 1.0 - GRIP_START, GRIP_HALF_WIDTH, STICK_COLOR);
+
+    for (i, point) in buffer.iter().enumerate() {
+        // older points fade out
+        let fade = 0.5 + i as f32 / buffer.len() as f32 / 2.0;
+        let color = [0.0, fade, 0.0, 1.0];
+        scene.trail.push(Vertex { position: [point[0], point[1], point[2]], color });
+    }
+    scene
+}
+
+const VERTEX_SHADER_SOURCE: &str = r#"
+    in vec3 aPos;
+    in vec4 aColor;
     out vec4 v_color;
-    uniform float u_angle;
     uniform mat4 u_matrix;
     void main() {
-        float prog = gl_VertexID / 100.0;
-        prog /= 2.0;
-        v_color = colors[gl_VertexID % 3] * (0.5 + prog);
-        if (gl_VertexID == 0 || gl_VertexID == 1) {
-            v_color = vec4(1.0, 0.0, 0.0, 1.0);
-        } else if (gl_VertexID == 2 || gl_VertexID == 3) {
-            v_color = vec4(0.0, 0.0, 1.0, 1.0);
-        } else if (gl_VertexID == 4 || gl_VertexID == 5) {
-            v_color = vec4(0.0, 1.0, 0.0, 1.0);
-        }
-        if (gl_VertexID < 6 && (gl_VertexID % 2) == 1) {
-            v_color *= 0.5;
-        }
-        gl_Position = vec4(aPos.xyz, 1.0);
+        v_color = aColor;
+        gl_Position = u_matrix * vec4(aPos, 1.0);
         gl_PointSize = 4.0;
-        //gl_Position.x *= cos(u_angle);
-        gl_Position = u_matrix * gl_Position;
     }
 "#;
 
@@ -98,6 +238,8 @@ impl GFX {
                 return None;
             }
             gl.attach_shader(program, fs);
+            gl.bind_attrib_location(program, 0, "aPos");
+            gl.bind_attrib_location(program, 1, "aColor");
             gl.link_program(program);
             assert!(gl.get_program_link_status(program), "{}", gl.get_program_info_log(program));
 
@@ -111,9 +253,11 @@ impl GFX {
 
             gl.bind_vertex_array(Some(vertex_array));
             gl.bind_buffer(glow::ARRAY_BUFFER, Some(vertex_buffer));
-            let stride = 4 * std::mem::size_of::<f32>() as i32;
+            let stride = std::mem::size_of::<Vertex>() as i32;
             gl.vertex_attrib_pointer_f32(0, 3, glow::FLOAT, false, stride, 0);
             gl.enable_vertex_attrib_array(0);
+            gl.vertex_attrib_pointer_f32(1, 4, glow::FLOAT, false, stride, 3 * std::mem::size_of::<f32>() as i32);
+            gl.enable_vertex_attrib_array(1);
             gl.bind_buffer(glow::ARRAY_BUFFER, None);
             gl.bind_vertex_array(None);
 
@@ -121,92 +265,129 @@ impl GFX {
         }
     }
 
-    fn paint(&self, gl: &glow::Context, angle: (f32, f32), buffer: [[f32; 4]; 100]) {
+    fn paint(&self, gl: &glow::Context, matrix: nalgebra_glm::Mat4, scene: &Scene) {
         use glow::HasContext as _;
         unsafe {
-            let proj = nalgebra_glm::perspective_fov(std::f32::consts::PI / 6.0, 10.0, 10.0, 1.0, 100.0);
-
-            let mut buffer1: [f32; 406] = [0.0; 406];
-            buffer1[0] = 1.0;
-            buffer1[4] = -1.0;
-
-            buffer1[10] = 1.0;
-            buffer1[14] = -1.0;
-
-            buffer1[17] = 1.0;
-            buffer1[21] = -1.0;
-            let mut i = 6;
-            while i < buffer.len() {
-                buffer1[(i * 4) + 0] = buffer[i][0];
-                buffer1[(i * 4) + 1] = buffer[i][1];
-                buffer1[(i * 4) + 2] = buffer[i][2];
-                buffer1[(i * 4) + 3] = buffer[i][3];
-                i += 1;
-            }
-
-            let view = nalgebra_glm::look_at(
-                &TVec3::new(f32::cos(angle.0) * 4.0, f32::sin(angle.1) * 4.0, f32::sin(angle.0) * 4.0),
-                &TVec3::new(0.0, 0.0, 0.0),
-                &TVec3::new(0.0, 1.0, 0.0),
-            );
+            let vertices: Vec<Vertex> = scene.lines.iter().chain(&scene.trail).copied().collect();
+            let trail_start = scene.lines.len() as i32;
+            let trail_length = scene.trail.len() as i32;
 
             gl.use_program(Some(self.program));
             gl.uniform_matrix_4_f32_slice(
                 gl.get_uniform_location(self.program, "u_matrix").as_ref(),
                 false,
-                (proj * view).data.as_slice(),
+                matrix.data.as_slice(),
             );
             gl.bind_vertex_array(Some(self.vertex_array));
             gl.bind_buffer(glow::ARRAY_BUFFER, Some(self.vertex_buffer));
-            gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, &buffer1.align_to::<u8>().1, glow::STATIC_DRAW);
+            gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, vertices.align_to::<u8>().1, glow::STREAM_DRAW);
 
             gl.line_width(1.0);
-            gl.draw_arrays(glow::LINES, 0, 6);
+            gl.draw_arrays(glow::LINES, 0, trail_start);
             gl.line_width(2.0);
-            gl.draw_arrays(glow::LINE_STRIP, 6, 99);
+            gl.draw_arrays(glow::LINE_STRIP, trail_start, trail_length);
             gl.enable(glow::VERTEX_PROGRAM_POINT_SIZE);
-            gl.draw_arrays(glow::POINTS, 105, 1);
+            gl.draw_arrays(glow::POINTS, trail_start + trail_length - 1, 1);
             gl.disable(glow::VERTEX_PROGRAM_POINT_SIZE);
         }
     }
 }
 
+/// Where `position` lands in `rect`, or `None` when it's behind the camera.
+fn project(rect: Rect, matrix: &nalgebra_glm::Mat4, position: [f32; 3]) -> Option<Pos2> {
+    let clip = matrix * nalgebra_glm::vec4(position[0], position[1], position[2], 1.0);
+    if clip.w <= f32::EPSILON {
+        return None;
+    }
+    let [x, y] = [clip.x / clip.w, clip.y / clip.w];
+    // normalized device coordinates have y going up
+    Some(rect.center() + vec2(x, -y) * rect.size() / 2.0)
+}
+
+fn color(vertex: &Vertex) -> Color32 {
+    let [r, g, b, a] = vertex.color;
+    Rgba::from_rgba_premultiplied(r, g, b, a).into()
+}
+
+/// Draws `scene` with egui's own shapes, for when there's no OpenGL context to paint into.
+#[profiling::function]
+fn paint_software(painter: &egui::Painter, rect: Rect, matrix: nalgebra_glm::Mat4, scene: &Scene) {
+    let segment = |from: &Vertex, to: &Vertex, width: f32| {
+        if let (Some(a), Some(b)) = (project(rect, &matrix, from.position), project(rect, &matrix, to.position)) {
+            painter.line_segment([a, b], Stroke::new(width, color(to)));
+        }
+    };
+    for pair in scene.lines.chunks_exact(2) {
+        segment(&pair[0], &pair[1], 1.0);
+    }
+    for pair in scene.trail.windows(2) {
+        segment(&pair[0], &pair[1], 2.0);
+    }
+    if let Some(newest) = scene.trail.last()
+        && let Some(point) = project(rect, &matrix, newest.position)
+    {
+        painter.circle_filled(point, 2.0, color(newest));
+    }
+}
+
 impl Graph3D {
-    pub fn new<'a>(cc: &'a eframe::CreationContext<'a>) -> Self { Self { gfx: GFX::new(cc), angle: (0.7, 0.3) } }
-
-    pub fn draw(&mut self, buffer: &[[f32; 4]; 100], ui: &mut egui::Ui) {
-        let (rect, response) =
-            ui.allocate_exact_size(egui::Vec2::splat(ui.spacing().interact_size.y * 10.0), egui::Sense::drag());
-
-        ui.painter().rect(rect, Rounding::ZERO, Color32::BLACK, ui.visuals().noninteractive().bg_stroke);
-
-        let gfx = match self.gfx.clone() {
-            Some(gfx) => gfx,
-            None => {
-                ui.painter().text(
-                    rect.center(),
-                    Align2::CENTER_CENTER,
-                    "Unavailable",
-                    FontId::proportional(ui.spacing().interact_size.y),
-                    Color32::WHITE,
-                );
-                return;
-            }
-        };
+    pub fn new<'a>(cc: &'a eframe::CreationContext<'a>) -> Self {
+        Self { gfx: GFX::new(cc), angle: DEFAULT_ANGLE, distance: DEFAULT_DISTANCE }
+    }
 
-        self.angle.0 += response.drag_motion().x * 0.01;
-        self.angle.1 += response.drag_motion().y * 0.01;
-        // Clone locals so we can move them into the paint callback:
-        let angle = self.angle;
-        let buffer = buffer.clone();
-        let cb = egui_glow::CallbackFn::new(move |_info, painter| {
-            gfx.lock().paint(painter.gl(), angle, buffer);
+    /// Projection and camera for the current view.
+    fn matrix(&self) -> nalgebra_glm::Mat4 {
+        let proj = nalgebra_glm::perspective_fov(PI / 6.0, 10.0, 10.0, 1.0, 100.0);
+        let (yaw, pitch) = self.angle;
+        let eye = TVec3::new(pitch.cos() * yaw.cos(), pitch.sin(), pitch.cos() * yaw.sin()) * self.distance;
+        let view = nalgebra_glm::look_at(&eye, &TVec3::new(0.0, 0.0, 0.0), &TVec3::new(0.0, 1.0, 0.0));
+        proj * view
+    }
+
+    fn controls(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            for (name, angle) in PRESETS {
+                if ui.small_button(name).clicked() {
+                    self.angle = angle;
+                }
+            }
+            if ui.small_button("Reset").on_hover_text("Default angle and zoom").clicked() {
+                self.angle = DEFAULT_ANGLE;
+                self.distance = DEFAULT_DISTANCE;
+            }
         });
+    }
 
-        let callback = egui::PaintCallback {
-            rect: rect.shrink(ui.visuals().noninteractive().bg_stroke.width),
-            callback: Arc::new(cb),
-        };
-        ui.painter().add(callback);
+    pub fn draw(&mut self, buffer: &[[f32; 4]; 100], model: &StickModel, ui: &mut egui::Ui) {
+        ui.vertical(|ui| {
+            let (rect, response) =
+                ui.allocate_exact_size(egui::Vec2::splat(ui.spacing().interact_size.y * 10.0), egui::Sense::drag());
+
+            ui.painter().rect(rect, Rounding::ZERO, Color32::BLACK, ui.visuals().noninteractive().bg_stroke);
+
+            self.angle.0 += response.drag_motion().x * 0.01;
+            self.angle.1 = (self.angle.1 + response.drag_motion().y * 0.01).clamp(-MAX_PITCH, MAX_PITCH);
+            if response.hovered() {
+                let (scroll, pinch) = ui.input(|input| (input.smooth_scroll_delta.y, input.zoom_delta()));
+                self.distance = (self.distance * 0.998f32.powf(scroll) / pinch)
+                    .clamp(*DISTANCE_RANGE.start(), *DISTANCE_RANGE.end());
+            }
+            self.controls(ui);
+
+            // Build everything up front so the paint callback only has to upload it
+            let matrix = self.matrix();
+            let scene = scene(buffer, model);
+            let inner = rect.shrink(ui.visuals().noninteractive().bg_stroke.width);
+            let Some(gfx) = self.gfx.clone() else {
+                paint_software(&ui.painter_at(inner), inner, matrix, &scene);
+                return;
+            };
+            let cb = egui_glow::CallbackFn::new(move |_info, painter| {
+                gfx.lock().paint(painter.gl(), matrix, &scene);
+            });
+
+            let callback = egui::PaintCallback { rect: inner, callback: Arc::new(cb) };
+            ui.painter().add(callback);
+        });
     }
 }
@@ -1,10 +1,25 @@
+mod attitude;
+mod calibration;
 mod graph;
 mod graph3d;
+mod monitor;
+mod noise;
+mod readback;
 
 use crate::{
-    pipe::{self, UI2VR, VR2UI, VRInputBounds, VRSystemFailure},
-    ui::graph3d::Graph3D,
-    util,
+    cli::Options,
+    mapping::{Pose, StickPipeline},
+    pipe::{
+        self, ControllerButton, DEFAULT_OUTPUT_RATE, EngageSettings, FailsafeBehavior, FailsafeSettings,
+        ForceFeedbackSettings, GAMEPAD_BUTTONS, GimbalSettings, HapticEvent, HapticSettings, InputReadings, KeyCombo,
+        KeyboardMouseSettings, KeyboardMouseStick, OUTPUT_RATES, OutputState, PoseSource, RecordingSettings,
+        ReferenceSpace, ReleaseBehavior, ReplayProgress, ReplaySettings, TrackingSettings, TrimSettings, UI2VR, VR2UI,
+        VRInputBounds, VRSystemFailure, VRTelemetry, YawReference,
+    },
+    recording,
+    telemetry::TelemetryReader,
+    ui::graph3d::{Graph3D, StickModel},
+    util::{self, runtime::Runtime},
 };
 use eframe::{emath::Align, epaint::Stroke};
 use egui::{
@@ -14,18 +29,69 @@ use egui::{
 };
 use log::info;
 use openxr::SystemProperties;
+use std::path::{Path, PathBuf};
 
 pub struct UI {
     tx: std::sync::mpsc::Sender<UI2VR>,
     rx: std::sync::mpsc::Receiver<VR2UI>,
+    telemetry: TelemetryReader<VRTelemetry>,
     startup_hand: pipe::Hand,
+    /// Runtimes found on the system
+    runtimes: Vec<Runtime>,
+    /// `None` starts whichever runtime is active
+    startup_runtime: Option<Runtime>,
     system_properties: Option<SystemProperties>,
     startup_failure: Option<VRSystemFailure>,
     runtime_failure: Option<VRSystemFailure>,
     graph3d: Graph3D,
     stick_bounds: VRInputBounds,
-    graph: [[f32; 4]; 100],
+    trim_settings: TrimSettings,
+    trim: [f32; 2],
+    engage_settings: EngageSettings,
+    engaged: bool,
+    failsafe_settings: FailsafeSettings,
+    tracked: bool,
+    tracking_losses: u32,
+    haptic_settings: HapticSettings,
+    ff_settings: ForceFeedbackSettings,
+    output_rate: u32,
+    tracking_settings: TrackingSettings,
+    recording_settings: RecordingSettings,
+    /// File being recorded to
+    recording: Option<PathBuf>,
+    /// Most recent file recorded to, for exporting
+    last_recording: Option<PathBuf>,
+    /// Result of the last CSV export
+    export_status: Option<String>,
+    /// Recording to replay, as typed
+    replay_path: String,
+    /// Why the last replay didn't start
+    replay_error: Option<String>,
+    replay_settings: ReplaySettings,
+    /// Where the running replay is
+    replay: Option<ReplayProgress>,
+    gimbal_settings: GimbalSettings,
+    kbm_settings: KeyboardMouseSettings,
+    /// Text being edited for each stick key combo
+    kbm_stick_text: [String; 4],
+    /// Text being edited for each button key combo
+    kbm_button_text: [String; 9],
+    graph: [Pose; 100],
+    /// What the game sees
+    output: OutputState,
+    /// Raw controller input, for the monitor
+    input_readings: InputReadings,
+    /// Recent stick output, as a fraction of full deflection
+    output_graph: [[f32; 4]; 100],
     id_mod: [f32; 3],
+    /// The calibration wizard, while it's open
+    calibration: Option<calibration::Calibration>,
+    /// The noise measurement, while it's open
+    noise: Option<noise::NoiseMeasurement>,
+    /// The virtual gamepad's input device under /sys, once it's been created
+    gamepad_device: Option<PathBuf>,
+    /// The virtual gamepad as read back from its device nodes, while that's open
+    readback: Option<readback::Readback>,
 }
 
 #[profiling::all_functions]
@@ -33,9 +99,21 @@ impl UI {
     pub fn new(
         tx: std::sync::mpsc::Sender<UI2VR>,
         rx: std::sync::mpsc::Receiver<VR2UI>,
+        telemetry: TelemetryReader<VRTelemetry>,
+        options: &Options,
         cc: &eframe::CreationContext,
     ) -> Self {
-        let id_mod = [0.0, 0.2, -1.0];
+        let kbm_settings = KeyboardMouseSettings::default();
+        let mut runtimes = util::runtime::discover();
+        let startup_runtime = options.pick_runtime().map(|picked| {
+            match runtimes.iter().find(|runtime| runtime.manifest == picked.manifest) {
+                Some(runtime) => runtime.clone(),
+                None => {
+                    runtimes.push(picked.clone());
+                    picked
+                }
+            }
+        });
 
         cc.egui_ctx.style_mut(|style| {
             for (style, font) in &mut style.text_styles {
@@ -58,20 +136,61 @@ impl UI {
         Self {
             tx,
             rx,
-            startup_hand: pipe::Hand::Right,
+            telemetry,
+            startup_hand: options.hand,
+            runtimes,
+            startup_runtime,
             system_properties: None,
             startup_failure: None,
             runtime_failure: None,
-            id_mod,
-            stick_bounds: VRInputBounds::default(),
-            graph: [[0.0; 4]; 100],
+            id_mod: options.up,
+            stick_bounds: options.bounds,
+            trim_settings: TrimSettings::default(),
+            trim: [0.0; 2],
+            engage_settings: EngageSettings::default(),
+            engaged: true,
+            failsafe_settings: FailsafeSettings::default(),
+            tracked: true,
+            tracking_losses: 0,
+            haptic_settings: HapticSettings::default(),
+            ff_settings: ForceFeedbackSettings::default(),
+            output_rate: DEFAULT_OUTPUT_RATE,
+            tracking_settings: TrackingSettings::default(),
+            recording_settings: RecordingSettings::default(),
+            recording: None,
+            last_recording: None,
+            export_status: None,
+            replay_path: String::new(),
+            replay_error: None,
+            replay_settings: ReplaySettings::default(),
+            replay: None,
+            gimbal_settings: GimbalSettings::default(),
+            kbm_settings,
+            kbm_stick_text: kbm_settings.stick_keys.map(|combo| combo.to_string()),
+            kbm_button_text: kbm_settings.button_keys.map(|combo| combo.to_string()),
+            graph: [Pose { orientation: [0.0; 4], position: None }; 100],
+            output: OutputState::default(),
+            input_readings: InputReadings::default(),
+            output_graph: [[0.0, 0.0, -2.0, 2.0]; 100],
+            calibration: None,
+            noise: None,
+            gamepad_device: None,
+            readback: None,
             graph3d: Graph3D::new(cc),
         }
     }
 
-    pub fn run(tx: std::sync::mpsc::Sender<UI2VR>, rx: std::sync::mpsc::Receiver<VR2UI>) -> eframe::Result<()> {
-        let rtn =
-            eframe::run_native("FlightWand", Default::default(), Box::new(|cc| Ok(Box::new(UI::new(tx, rx, cc)))));
+    pub fn run(
+        tx: std::sync::mpsc::Sender<UI2VR>,
+        rx: std::sync::mpsc::Receiver<VR2UI>,
+        telemetry: TelemetryReader<VRTelemetry>,
+        options: &Options,
+    ) -> eframe::Result<()> {
+        let rtn = eframe::run_native(
+            "FlightWand",
+            Default::default(),
+            Box::new(|cc| Ok(Box::new(UI::new(tx, rx, telemetry, options, cc)))),
+        );
         info!("Frontend stopped");
         rtn
     }
@@ -79,7 +198,68 @@ impl UI {
 
 #[profiling::all_functions]
 impl UI {
+    /// The stick processing as the backend does it, with the settings shown.
+    fn stick_pipeline(&self) -> StickPipeline {
+        StickPipeline { up: self.id_mod, bounds: self.stick_bounds, gimbal: self.gimbal_settings }
+    }
+
     fn main_content(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if self.tracked {
+                ui.colored_label(Color32::GREEN, "Tracking");
+            } else {
+                ui.colored_label(Color32::RED, "Tracking lost");
+            }
+            ui.label(format!("Lost {} times", self.tracking_losses));
+        });
+
+        let before = self.tracking_settings;
+        ui.horizontal(|ui| {
+            ui.label("Pose: ");
+            egui::ComboBox::from_id_salt("PoseSource")
+                .selected_text(format!("{}", self.tracking_settings.pose))
+                .show_ui(ui, |ui| {
+                    for pose in [PoseSource::Aim, PoseSource::Grip] {
+                        ui.selectable_value(&mut self.tracking_settings.pose, pose, format!("{}", pose));
+                    }
+                });
+            ui.label("Relative to: ");
+            egui::ComboBox::from_id_salt("ReferenceSpace")
+                .selected_text(format!("{}", self.tracking_settings.space))
+                .show_ui(ui, |ui| {
+                    for space in ReferenceSpace::ALL {
+                        ui.selectable_value(&mut self.tracking_settings.space, space, format!("{}", space));
+                    }
+                });
+        });
+        ui.horizontal(|ui| {
+            ui.label("Forward follows: ");
+            egui::ComboBox::from_id_salt("YawReference")
+                .selected_text(format!("{}", self.tracking_settings.yaw))
+                .show_ui(ui, |ui| {
+                    for yaw in YawReference::ALL {
+                        ui.selectable_value(&mut self.tracking_settings.yaw, yaw, format!("{}", yaw));
+                    }
+                });
+            match self.tracking_settings.yaw {
+                YawReference::None => {}
+                YawReference::Head => {
+                    Slider::new(&mut self.tracking_settings.yaw_smoothing, 0.0..=3.0)
+                        .suffix(" s")
+                        .text("Smoothing")
+                        .ui(ui);
+                }
+                YawReference::Seat => {
+                    if ui.button("Capture Seat").on_hover_text("Use the way you're facing now as forward").clicked() {
+                        let _ = self.tx.send(UI2VR::CaptureSeat);
+                    }
+                }
+            }
+        });
+        if self.tracking_settings != before {
+            let _ = self.tx.send(UI2VR::UpdateTracking(self.tracking_settings));
+        }
+
         ui.label("Up: ");
         ui.horizontal(|ui| {
             ui.spacing_mut().slider_width = (ui.available_width() - ui.spacing().item_spacing.x * 2.0) / 3.0;
@@ -107,49 +287,146 @@ impl UI {
                 let _ = self.tx.send(UI2VR::UpdateIdentity(self.id_mod));
             }
         });
+        ui.horizontal(|ui| {
+            if ui.button("Recenter").on_hover_text("Use the controller's current orientation as neutral").clicked() {
+                let _ = self.tx.send(UI2VR::Recenter);
+            }
+            if ui
+                .add_enabled(self.calibration.is_none(), egui::Button::new("Calibrate..."))
+                .on_hover_text("Find neutral and how far the stick goes each way, step by step")
+                .clicked()
+            {
+                self.calibration = Some(calibration::Calibration::new());
+            }
+        });
 
-        ui.label("Current rotation: ");
+        let gimbal = &mut self.gimbal_settings;
+        let mut gimbal_changed = false;
+        ui.horizontal(|ui| {
+            gimbal_changed |= ui
+                .checkbox(&mut gimbal.enabled, "Virtual gimbal")
+                .on_hover_text("Move the controller around a pivot like the top of a floor-mounted stick")
+                .changed();
+            if ui.button("Capture Pivot").on_hover_text("Put the pivot one stick length below the controller").clicked()
+            {
+                let _ = self.tx.send(UI2VR::CapturePivot);
+            }
+        });
+        if gimbal.enabled {
+            ui.horizontal(|ui| {
+                ui.label("Pivot: ");
+                for axis in &mut gimbal.pivot {
+                    gimbal_changed |= DragValue::new(axis).speed(0.005).suffix(" m").ui(ui).changed();
+                }
+            });
+            let length = gimbal.length;
+            if Slider::new(&mut gimbal.length, 0.05..=1.0).suffix(" m").text("Stick length").ui(ui).changed() {
+                // keep the top of the stick where the controller is
+                gimbal.pivot[1] -= gimbal.length - length;
+                gimbal_changed = true;
+            }
+            gimbal_changed |=
+                Slider::new(&mut gimbal.orientation_blend, 0.0..=1.0).text("Wrist orientation").ui(ui).changed();
+        }
+        if gimbal_changed {
+            let _ = self.tx.send(UI2VR::UpdateGimbal(*gimbal));
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Current rotation: ");
+            let stats = self.telemetry.stats();
+            ui.weak(format!("{}/{} queued, {} dropped", stats.depth, stats.capacity, stats.dropped)).on_hover_text(
+                "Telemetry waiting at the last repaint, and thrown away while the window wasn't keeping up",
+            );
+        });
         let mut buffer: [[f32; 4]; 100] = [[0.0; 4]; 100];
         ui.with_layout(Layout::right_to_left(Align::Min), |ui| {
             profiling::scope!("Rotation visualization");
+            let pipeline = self.stick_pipeline();
             let mut i = 0;
             while i < self.graph.len() {
                 profiling::scope!(&format!("data point {}", i));
-                buffer[i] = util::modifier(&self.graph[i], self.id_mod);
+                buffer[i] = pipeline.direction(&self.graph[i]);
                 i += 1;
             }
-            self.graph3d.draw(&buffer, ui);
+            let model = StickModel { stick: self.output.stick(), bounds: self.stick_bounds };
+            self.graph3d.draw(&buffer, &model, ui);
+            attitude::attitude_indicator(&buffer[buffer.len() - 1], &self.stick_bounds, ui);
             graph::graph(&buffer, self.id_mod, ui, |a, _| *a);
         });
 
-        ui.label("Gamepad output: ");
+        ui.horizontal(|ui| {
+            ui.label("Gamepad output: ");
+            let before = self.output_rate;
+            egui::ComboBox::from_id_salt("OutputRate").selected_text(format!("{} Hz", self.output_rate)).show_ui(
+                ui,
+                |ui| {
+                    for rate in OUTPUT_RATES {
+                        ui.selectable_value(&mut self.output_rate, rate, format!("{} Hz", rate));
+                    }
+                },
+            );
+            if self.output_rate != before {
+                let _ = self.tx.send(UI2VR::UpdateOutputRate(self.output_rate));
+            }
+            if let Some(device) = &self.gamepad_device
+                && ui
+                    .add_enabled(self.readback.is_none(), egui::Button::new("Read back..."))
+                    .on_hover_text("Open the virtual gamepad the way a game would, to check what it reports")
+                    .clicked()
+            {
+                self.readback = Some(readback::Readback::open(device.clone()));
+            }
+        });
         if ui
             .add_sized(
                 vec2(ui.available_width(), ui.spacing().interact_size.y),
                 Slider::new(&mut self.stick_bounds.deadzone, 0.0..=1.0).text("Deadzone"),
             )
             .changed()
-            || ui
-                .add_sized(
-                    vec2(ui.available_width(), ui.spacing().interact_size.y),
-                    Slider::new(&mut self.stick_bounds.stick_max, 0.0..=1.0).text("Maximum"),
-                )
-                .changed()
+            || match self.stick_bounds.ranges {
+                Some(ranges) => {
+                    ui.horizontal(|ui| {
+                        ui.label(format!(
+                            "Maximum: {:.2} right, {:.2} left, {:.2} forward, {:.2} back",
+                            ranges.right, ranges.left, ranges.forward, ranges.back
+                        ));
+                        let symmetric = ui.button("Use one maximum").on_hover_text("Forget the calibrated ranges");
+                        if symmetric.clicked() {
+                            self.stick_bounds.ranges = None;
+                        }
+                        symmetric.clicked()
+                    })
+                    .inner
+                }
+                None => ui
+                    .add_sized(
+                        vec2(ui.available_width(), ui.spacing().interact_size.y),
+                        Slider::new(&mut self.stick_bounds.stick_max, 0.0..=1.0).text("Maximum"),
+                    )
+                    .changed(),
+            }
         {
-            let _ = self.tx.send(UI2VR::UpdateBounds(self.stick_bounds.clone()));
+            let _ = self.tx.send(UI2VR::UpdateBounds(self.stick_bounds));
         }
+        ui.horizontal(|ui| {
+            let smoothing = Slider::new(&mut self.stick_bounds.smoothing, 0.0..=0.2)
+                .text("Smoothing (s)")
+                .ui(ui)
+                .on_hover_text("Smooths out tracking jitter before the deadzone, at the cost of some delay");
+            if smoothing.changed() {
+                let _ = self.tx.send(UI2VR::UpdateBounds(self.stick_bounds));
+            }
+            if ui
+                .add_enabled(self.noise.is_none(), egui::Button::new("Measure noise..."))
+                .on_hover_text("Hold the controller still to find a deadzone that keeps its jitter out")
+                .clicked()
+            {
+                self.noise = Some(noise::NoiseMeasurement::new());
+            }
+        });
         ui.with_layout(Layout::right_to_left(Align::Min), |ui| {
             profiling::scope!("final joystick visualization");
-            let mut i = 0;
-            while i < self.graph.len() {
-                profiling::scope!(&format!("data point {}", i));
-                let tmp = util::modifier(&self.graph[i], self.id_mod);
-                let [x, y] = util::rot_to_joy(&[tmp[0], tmp[2]], self.stick_bounds);
-
-                buffer[i] = [x, y, -2.0, 2.0];
-                i += 1;
-            }
-
             let (rect, _) =
                 ui.allocate_exact_size(egui::Vec2::splat(ui.spacing().interact_size.y * 10.0), egui::Sense::click());
 
@@ -169,26 +446,323 @@ impl UI {
                 Color32::TRANSPARENT,
                 Stroke::new(1.0, Color32::from_rgb(0, 128, 200)),
             );
-            ui.painter().circle(
-                rect.center(),
-                (rect.width() / 2.0) * self.stick_bounds.stick_max,
-                Color32::TRANSPARENT,
-                Stroke::new(1.0, Color32::GOLD),
-            );
-            let plt_x = buffer[99][0] * rect.width() / 2.0;
-            let plt_y = 0.0 - buffer[99][1] * rect.width() / 2.0;
+            // full output is at the maximum ring, like the trim below
+            let bounds = self.stick_bounds;
+            let plot = |stick: [f32; 2]| {
+                let [max_x, max_y] = bounds.max_for(stick);
+                rect.center() + vec2(stick[0] * max_x, -stick[1] * max_y) * (rect.width() / 2.0)
+            };
+            let ring = (0..64).map(|i| i as f32 / 64.0 * std::f32::consts::TAU).map(|a| plot([a.cos(), a.sin()]));
+            ui.painter().add(egui::Shape::closed_line(ring.collect(), Stroke::new(1.0, Color32::GOLD)));
+            let dot = if self.output.live { Color32::WHITE } else { Color32::GRAY };
+            ui.painter().circle_filled(plot(self.output.stick()), 4.0, dot);
+
+            // the trim offset is in output units, where the edge of the output is the maximum ring
+            let trim_pos = plot(self.trim);
+            let trim_stroke = Stroke::new(2.0, Color32::from_rgb(0, 200, 100));
+            ui.painter().line_segment([trim_pos - vec2(6.0, 0.0), trim_pos + vec2(6.0, 0.0)], trim_stroke);
+            ui.painter().line_segment([trim_pos - vec2(0.0, 6.0), trim_pos + vec2(0.0, 6.0)], trim_stroke);
+
+            graph::graph(&self.output_graph, self.id_mod, ui, |a, _| *a);
+        });
+        ui.horizontal_wrapped(|ui| {
+            for ((_, key), pressed) in GAMEPAD_BUTTONS.iter().zip(self.output.buttons) {
+                let name = format!("{key:?}");
+                let name = name.trim_start_matches("BTN_");
+                if pressed {
+                    ui.colored_label(Color32::BLACK, egui::RichText::new(name).background_color(Color32::GOLD));
+                } else {
+                    ui.weak(name);
+                }
+            }
+            if !self.output.live {
+                ui.colored_label(Color32::GRAY, "Not sent to the game");
+            }
+        });
+
+        ui.label("Controller input: ");
+        monitor::monitor(&self.input_readings, ui);
+
+        ui.label("Trim: ");
+        ui.horizontal(|ui| {
+            ui.label(format!("Offset: {:+.3}, {:+.3}", self.trim[0], self.trim[1]));
+            if ui.button("Reset").clicked() {
+                let _ = self.tx.send(UI2VR::ResetTrim);
+            }
+        });
+        let mut trim_changed = ui
+            .add_sized(
+                vec2(ui.available_width(), ui.spacing().interact_size.y),
+                Slider::new(&mut self.trim_settings.step, 0.001..=0.1).text("Step"),
+            )
+            .changed();
+        egui::Grid::new("TrimBindings").num_columns(2).show(ui, |ui| {
+            let bindings = [
+                ("Trim up", &mut self.trim_settings.up),
+                ("Trim down", &mut self.trim_settings.down),
+                ("Trim left", &mut self.trim_settings.left),
+                ("Trim right", &mut self.trim_settings.right),
+                ("Force trim", &mut self.trim_settings.force_trim),
+                ("Reset trim", &mut self.trim_settings.reset),
+            ];
+            for (name, button) in bindings {
+                ui.label(name);
+                trim_changed |= button_combo(ui, name, button);
+                ui.end_row();
+            }
+        });
+        if trim_changed {
+            let _ = self.tx.send(UI2VR::UpdateTrim(self.trim_settings));
+        }
+
+        ui.label("Engage: ");
+        if self.engaged {
+            ui.colored_label(Color32::GREEN, "Stick engaged");
+        } else {
+            ui.colored_label(Color32::GOLD, "Stick released");
+        }
+        let mut engage_changed = false;
+        egui::Grid::new("EngageSettings").num_columns(2).show(ui, |ui| {
+            ui.label("Hold to engage");
+            engage_changed |= button_combo(ui, "EngageButton", &mut self.engage_settings.button);
+            ui.end_row();
+
+            ui.label("On release");
+            let before = self.engage_settings.release;
+            egui::ComboBox::from_id_salt("ReleaseBehavior")
+                .selected_text(format!("{}", self.engage_settings.release))
+                .show_ui(ui, |ui| {
+                    for option in [ReleaseBehavior::Freeze, ReleaseBehavior::Recenter, ReleaseBehavior::HoldLast] {
+                        ui.selectable_value(&mut self.engage_settings.release, option, format!("{}", option));
+                    }
+                });
+            engage_changed |= self.engage_settings.release != before;
+            ui.end_row();
+        });
+        engage_changed |= ui.checkbox(&mut self.engage_settings.recapture_neutral, "Recenter when engaging").changed();
+        if engage_changed {
+            let _ = self.tx.send(UI2VR::UpdateEngage(self.engage_settings));
+        }
+
+        ui.label("Tracking loss: ");
+        let mut failsafe_changed = false;
+        ui.horizontal(|ui| {
+            let before = self.failsafe_settings.behavior;
+            egui::ComboBox::from_id_salt("FailsafeBehavior")
+                .selected_text(format!("{}", self.failsafe_settings.behavior))
+                .show_ui(ui, |ui| {
+                    for option in FailsafeBehavior::ALL {
+                        ui.selectable_value(&mut self.failsafe_settings.behavior, option, format!("{}", option));
+                    }
+                });
+            failsafe_changed |= self.failsafe_settings.behavior != before;
+            if self.failsafe_settings.behavior == FailsafeBehavior::Decay {
+                failsafe_changed |= Slider::new(&mut self.failsafe_settings.decay, 0.0..=3000.0)
+                    .suffix(" ms")
+                    .text("Decay")
+                    .ui(ui)
+                    .changed();
+            }
+        });
+        if failsafe_changed {
+            let _ = self.tx.send(UI2VR::UpdateFailsafe(self.failsafe_settings));
+        }
+
+        ui.label("Haptics: ");
+        let mut haptics_changed = false;
+        egui::Grid::new("HapticSettings").num_columns(5).show(ui, |ui| {
+            ui.label("");
+            ui.label("Amplitude");
+            ui.label("Duration (ms)");
+            ui.label("Frequency (Hz)");
+            ui.end_row();
 
-            ui.painter().circle_filled(rect.center() + vec2(plt_x, plt_y), 4.0, Color32::WHITE);
+            for event in HapticEvent::ALL {
+                let pulse = self.haptic_settings.pulse_mut(event);
+                haptics_changed |= ui.checkbox(&mut pulse.enabled, format!("{}", event)).changed();
+                haptics_changed |= Slider::new(&mut pulse.amplitude, 0.0..=1.0).ui(ui).changed();
+                haptics_changed |= DragValue::new(&mut pulse.duration).range(1.0..=1000.0).ui(ui).changed();
+                haptics_changed |= DragValue::new(&mut pulse.frequency)
+                    .range(0.0..=1000.0)
+                    .custom_formatter(|n, _| if n == 0.0 { "Default".to_owned() } else { format!("{}", n) })
+                    .ui(ui)
+                    .changed();
+                if ui.button("Test").clicked() {
+                    let _ = self.tx.send(UI2VR::TestHaptic(*pulse));
+                }
+                ui.end_row();
+            }
+        });
+        haptics_changed |= ui
+            .add_sized(
+                vec2(ui.available_width(), ui.spacing().interact_size.y),
+                Slider::new(&mut self.haptic_settings.throttle_detent, 0.0..=1.0).text("Throttle detent"),
+            )
+            .changed();
+        if haptics_changed {
+            let _ = self.tx.send(UI2VR::UpdateHaptics(self.haptic_settings));
+        }
 
-            graph::graph(&buffer, self.id_mod, ui, |a, _| {
-                let length = f32::sqrt(a[0] * a[0] + a[1] * a[1]);
+        let mut ff_changed = ui.checkbox(&mut self.ff_settings.enabled, "Forward game rumble").changed();
+        ff_changed |= ui
+            .add_sized(
+                vec2(ui.available_width(), ui.spacing().interact_size.y),
+                Slider::new(&mut self.ff_settings.gain, 0.0..=2.0).text("Rumble gain"),
+            )
+            .changed();
+        if ff_changed {
+            let _ = self.tx.send(UI2VR::UpdateForceFeedback(self.ff_settings));
+        }
 
-                let x = if length < self.stick_bounds.deadzone { 0.0 } else { a[0] / self.stick_bounds.stick_max };
-                let y = if length < self.stick_bounds.deadzone { 0.0 } else { a[1] / self.stick_bounds.stick_max };
+        ui.label("Recording: ");
+        ui.horizontal(|ui| match &self.recording {
+            Some(path) => {
+                if ui.button("Stop").clicked() {
+                    let _ = self.tx.send(UI2VR::StopRecording);
+                }
+                ui.colored_label(Color32::RED, format!("Recording to {}", path.display()));
+            }
+            None => {
+                if ui.button("Record").on_hover_text("Record every pose, input and output to a file").clicked() {
+                    let _ = self.tx.send(UI2VR::StartRecording);
+                }
+                if let Some(path) = &self.last_recording
+                    && ui.button("Export CSV").on_hover_text(path.display().to_string()).clicked()
+                {
+                    self.export_status = Some(match recording::export_csv(path) {
+                        Ok(csv) => format!("Exported to {}", csv.display()),
+                        Err(err) => format!("Export failed: {}", err),
+                    });
+                }
+            }
+        });
+        if let Some(status) = &self.export_status {
+            ui.label(status);
+        }
+        egui::Grid::new("RecordingSettings").num_columns(2).show(ui, |ui| {
+            ui.label("Start/stop");
+            if button_combo(ui, "RecordButton", &mut self.recording_settings.button) {
+                let _ = self.tx.send(UI2VR::UpdateRecording(self.recording_settings));
+            }
+            ui.end_row();
+        });
 
-                [x, y, a[2], a[3]]
+        ui.label("Replay: ");
+        match self.replay {
+            Some(progress) => {
+                ui.horizontal(|ui| {
+                    if ui.button("Stop").clicked() {
+                        let _ = self.tx.send(UI2VR::StopReplay);
+                    }
+                    if progress.finished {
+                        ui.label("Finished");
+                    }
+                });
+                let mut position = progress.position;
+                let scrub = ui.add(
+                    Slider::new(&mut position, 0.0..=progress.length).text("Position").suffix(" s").fixed_decimals(1),
+                );
+                if scrub.changed() {
+                    let _ = self.tx.send(UI2VR::SeekReplay(position));
+                }
+            }
+            None => {
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut self.replay_path).on_hover_text("Path to a .fwrec recording");
+                    if let Some(path) = &self.last_recording
+                        && ui.button("Last recording").clicked()
+                    {
+                        self.replay_path = path.display().to_string();
+                    }
+                    if ui.add_enabled(!self.replay_path.is_empty(), egui::Button::new("Replay")).clicked() {
+                        // read here, so the output thread doesn't stall on it
+                        self.replay_error = match recording::read(Path::new(&self.replay_path)) {
+                            Ok((_, frames)) if !frames.is_empty() => {
+                                let _ = self.tx.send(UI2VR::StartReplay(frames));
+                                None
+                            }
+                            Ok(_) => Some(format!("{} has nothing to replay", self.replay_path)),
+                            Err(err) => Some(format!("Couldn't replay {}: {}", self.replay_path, err)),
+                        };
+                    }
+                });
+                if let Some(error) = &self.replay_error {
+                    ui.colored_label(Color32::RED, error);
+                }
+            }
+        }
+        let replay = &mut self.replay_settings;
+        let replay_changed = ui.checkbox(&mut replay.paused, "Paused").changed()
+            | ui.add(Slider::new(&mut replay.speed, 0.1..=8.0).text("Speed").suffix("×").logarithmic(true)).changed()
+            | ui.checkbox(&mut replay.drive_gamepad, "Send to the virtual gamepad")
+                .on_hover_text("Otherwise the replay is only shown here")
+                .changed();
+        if replay_changed {
+            let _ = self.tx.send(UI2VR::UpdateReplay(self.replay_settings));
+        }
+
+        ui.label("Keyboard & mouse: ");
+        let kbm = &mut self.kbm_settings;
+        let mut kbm_changed = ui.checkbox(&mut kbm.enabled, "Enable virtual keyboard/mouse").changed();
+        ui.add_enabled_ui(kbm.enabled, |ui| {
+            kbm_changed |= ui.checkbox(&mut kbm.replace_joystick, "Disable the virtual joystick").changed();
+            ui.horizontal(|ui| {
+                ui.label("Stick as");
+                let before = kbm.stick;
+                egui::ComboBox::from_id_salt("KeyboardMouseStick").selected_text(format!("{}", kbm.stick)).show_ui(
+                    ui,
+                    |ui| {
+                        for option in [KeyboardMouseStick::Mouse, KeyboardMouseStick::Keys] {
+                            ui.selectable_value(&mut kbm.stick, option, format!("{}", option));
+                        }
+                    },
+                );
+                kbm_changed |= kbm.stick != before;
+            });
+            match kbm.stick {
+                KeyboardMouseStick::Mouse => {
+                    kbm_changed |= ui
+                        .add_sized(
+                            vec2(ui.available_width(), ui.spacing().interact_size.y),
+                            Slider::new(&mut kbm.sensitivity, 10.0..=10000.0).logarithmic(true).text("Sensitivity"),
+                        )
+                        .changed();
+                    kbm_changed |= ui
+                        .add_sized(
+                            vec2(ui.available_width(), ui.spacing().interact_size.y),
+                            Slider::new(&mut kbm.acceleration, 0.5..=4.0).text("Acceleration"),
+                        )
+                        .changed();
+                    kbm_changed |= ui.checkbox(&mut kbm.invert_y, "Invert Y").changed();
+                }
+                KeyboardMouseStick::Keys => {
+                    kbm_changed |= ui
+                        .add_sized(
+                            vec2(ui.available_width(), ui.spacing().interact_size.y),
+                            Slider::new(&mut kbm.pulse_period, 20.0..=500.0).text("Pulse period (ms)"),
+                        )
+                        .changed();
+                }
+            }
+            egui::Grid::new("KeyboardMouseKeys").num_columns(2).show(ui, |ui| {
+                if kbm.stick == KeyboardMouseStick::Keys {
+                    for (i, name) in ["Stick up", "Stick down", "Stick left", "Stick right"].into_iter().enumerate() {
+                        ui.label(name);
+                        kbm_changed |= key_combo_edit(ui, &mut self.kbm_stick_text[i], &mut kbm.stick_keys[i]);
+                        ui.end_row();
+                    }
+                }
+                for button in ControllerButton::ALL.into_iter().skip(1) {
+                    ui.label(format!("{}", button));
+                    kbm_changed |=
+                        key_combo_edit(ui, &mut self.kbm_button_text[button as usize], kbm.button_keys_mut(button));
+                    ui.end_row();
+                }
             });
         });
+        if kbm_changed {
+            let _ = self.tx.send(UI2VR::UpdateKeyboardMouse(self.kbm_settings));
+        }
     }
 
     fn render_failure(&self, ctx: &eframe::egui::Context, failure: &VRSystemFailure) {
@@ -208,10 +782,74 @@ impl UI {
     }
 }
 
+/// Combo box for assigning a controller button to a function. Returns whether the assignment changed.
+fn button_combo(ui: &mut egui::Ui, id: &str, button: &mut ControllerButton) -> bool {
+    let before = *button;
+    egui::ComboBox::from_id_salt(id).selected_text(format!("{}", button)).show_ui(ui, |ui| {
+        for option in ControllerButton::ALL {
+            ui.selectable_value(button, option, format!("{}", option));
+        }
+    });
+    *button != before
+}
+
+/// Text field for a key combo. The combo is only updated (and `true` returned) when the text parses.
+fn key_combo_edit(ui: &mut egui::Ui, text: &mut String, combo: &mut KeyCombo) -> bool {
+    let response = ui.text_edit_singleline(text);
+    match text.parse::<KeyCombo>() {
+        Ok(parsed) => {
+            let changed = parsed != *combo;
+            *combo = parsed;
+            changed
+        }
+        Err(err) => {
+            ui.painter().rect_stroke(response.rect, Rounding::ZERO, Stroke::new(1.0, Color32::RED));
+            response.on_hover_text(err);
+            false
+        }
+    }
+}
+
 #[profiling::all_functions]
 impl eframe::App for UI {
     fn update(&mut self, ctx: &eframe::egui::Context, _frame: &mut eframe::Frame) {
         ctx.request_repaint();
+        // before the messages, so progress left over from a stopped replay doesn't bring it back
+        for sample in self.telemetry.drain() {
+            match sample {
+                VRTelemetry::Rotation(quat, position) => {
+                    let mut i = 0;
+                    while i < 99 {
+                        self.graph[i] = self.graph[i + 1];
+                        i += 1;
+                    }
+                    let pose = Pose { orientation: [quat.x, quat.y, quat.z, quat.w], position };
+                    let pipeline = self.stick_pipeline();
+                    if let Some(calibration) = &mut self.calibration {
+                        calibration.feed(&pose, &pipeline);
+                    }
+                    if let Some(noise) = &mut self.noise {
+                        noise.feed(&pose, &pipeline);
+                    }
+                    self.graph[99] = pose;
+                }
+                VRTelemetry::Input(readings) => {
+                    self.input_readings = readings;
+                }
+                VRTelemetry::Output(output) => {
+                    self.output_graph.rotate_left(1);
+                    let [x, y] = output.stick();
+                    // the last two columns are out of the graph's bounds
+                    self.output_graph[99] = [x, y, -2.0, 2.0];
+                    self.output = output;
+                }
+                VRTelemetry::ReplayProgress(progress) => {
+                    if self.replay.is_some() {
+                        self.replay = Some(progress);
+                    }
+                }
+            }
+        }
         while let Ok(msg) = self.rx.try_recv() {
             match msg {
                 VR2UI::Running(inf) => {
@@ -224,16 +862,37 @@ impl eframe::App for UI {
                         self.runtime_failure = Some(inf);
                     }
                 }
-                VR2UI::RotationUpdate(quat) => {
-                    let mut i = 0;
-                    while i < 99 {
-                        self.graph[i] = self.graph[i + 1];
-                        i += 1;
+                VR2UI::TrimUpdate(trim) => {
+                    self.trim = trim;
+                }
+                VR2UI::IdentityUpdate(id) => {
+                    self.id_mod = id;
+                }
+                VR2UI::BoundsUpdate(bounds) => {
+                    self.stick_bounds = bounds;
+                }
+                VR2UI::EngageUpdate(engaged) => {
+                    self.engaged = engaged;
+                }
+                VR2UI::RecordingUpdate(path) => {
+                    if path.is_some() {
+                        self.last_recording = path.clone();
+                        self.export_status = None;
                     }
-                    self.graph[99][0] = quat.x;
-                    self.graph[99][1] = quat.y;
-                    self.graph[99][2] = quat.z;
-                    self.graph[99][3] = quat.w;
+                    self.recording = path;
+                }
+                VR2UI::ReplayUpdate(progress) => {
+                    self.replay = progress;
+                }
+                VR2UI::PivotUpdate(pivot) => {
+                    self.gimbal_settings.pivot = pivot;
+                }
+                VR2UI::GamepadDevice(path) => {
+                    self.gamepad_device = Some(path);
+                }
+                VR2UI::TrackingUpdate(tracked, losses) => {
+                    self.tracked = tracked;
+                    self.tracking_losses = losses;
                 }
             }
         }
@@ -245,7 +904,7 @@ impl eframe::App for UI {
 
             match &self.system_properties {
                 Some(inf) => {
-                    ui.heading(format!("{}", &inf.system_name));
+                    ui.heading(&inf.system_name);
                     ui.separator();
                 }
                 None => {
@@ -253,7 +912,7 @@ impl eframe::App for UI {
 
                     let rect = egui::Rect::from_center_size(
                         ui.available_rect_before_wrap().center(),
-                        vec2(200.0, 50.0 + ui.spacing().item_spacing.y + ui.spacing().interact_size.y),
+                        vec2(200.0, 50.0 + ui.spacing().item_spacing.y * 2.0 + ui.spacing().interact_size.y * 2.0),
                     );
                     let cursor = vec2(
                         (ui.available_rect_before_wrap().width() - rect.width()) / 2.0 - ui.spacing().item_spacing.x,
@@ -265,6 +924,26 @@ impl eframe::App for UI {
                         ui.vertical(|ui| {
                             ui.allocate_space(cursor);
 
+                            let runtime_text = match &self.startup_runtime {
+                                Some(runtime) => runtime.name.clone(),
+                                None => "Active Runtime".to_owned(),
+                            };
+                            egui::ComboBox::from_id_salt("RuntimeComboBox")
+                                .width(200.0)
+                                .selected_text(runtime_text)
+                                .show_ui(ui, |ui| {
+                                    ui.selectable_value(&mut self.startup_runtime, None, "Active Runtime");
+                                    for runtime in &self.runtimes {
+                                        let label = if runtime.active {
+                                            format!("{} (active)", runtime.name)
+                                        } else {
+                                            runtime.name.clone()
+                                        };
+                                        ui.selectable_value(&mut self.startup_runtime, Some(runtime.clone()), label)
+                                            .on_hover_text(runtime.manifest.display().to_string());
+                                    }
+                                });
+
                             egui::ComboBox::from_id_salt("HandComboBox")
                                 .width(200.0)
                                 .height(50.0)
@@ -275,7 +954,15 @@ impl eframe::App for UI {
                                 });
 
                             if ui.add_sized(vec2(200.0, 50.0), egui::Button::new("Start")).clicked() {
-                                let _ = self.tx.send(UI2VR::Start(self.startup_hand.clone()));
+                                let manifest = self.startup_runtime.as_ref().map(|runtime| runtime.manifest.as_path());
+                                if let Err(err) = util::runtime::remember(manifest) {
+                                    log::warn!("Couldn't remember the OpenXR runtime: {err}");
+                                }
+                                let _ = self.tx.send(UI2VR::SelectRuntime(self.startup_runtime.clone()));
+                                let _ = self.tx.send(UI2VR::Start(self.startup_hand));
+                                // settings sent before starting are dropped, these wait until the backend is up
+                                let _ = self.tx.send(UI2VR::UpdateBounds(self.stick_bounds));
+                                let _ = self.tx.send(UI2VR::UpdateIdentity(self.id_mod));
                             }
                         });
                     });
@@ -288,12 +975,61 @@ impl eframe::App for UI {
                 if self.runtime_failure.is_some() {
                     ui.disable();
                 }
-                self.main_content(ui);
+                egui::ScrollArea::vertical().show(ui, |ui| self.main_content(ui));
             });
 
             profiling::finish_frame!();
         });
 
+        let pipeline = self.stick_pipeline();
+        if let Some(calibration) = &mut self.calibration {
+            let mut open = true;
+            let outcome = egui::Window::new("Calibration")
+                .open(&mut open)
+                .collapsible(false)
+                .show(ctx, |ui| calibration.show(&pipeline, ui))
+                .and_then(|response| response.inner);
+            match outcome {
+                Some(calibration::Outcome::Apply(calibrated)) => {
+                    self.id_mod = calibrated.up;
+                    self.stick_bounds.ranges = Some(calibrated.ranges);
+                    let _ = self.tx.send(UI2VR::UpdateIdentity(self.id_mod));
+                    let _ = self.tx.send(UI2VR::UpdateBounds(self.stick_bounds));
+                    self.calibration = None;
+                }
+                Some(calibration::Outcome::Cancel) => self.calibration = None,
+                _ if !open => self.calibration = None,
+                _ => {}
+            }
+        }
+
+        if let Some(noise) = &mut self.noise {
+            let mut open = true;
+            let outcome = egui::Window::new("Tracking noise")
+                .open(&mut open)
+                .collapsible(false)
+                .show(ctx, |ui| noise.show(&self.stick_bounds, ui))
+                .and_then(|response| response.inner);
+            match outcome {
+                Some(noise::Outcome::Apply { deadzone, smoothing }) => {
+                    self.stick_bounds.deadzone = deadzone;
+                    self.stick_bounds.smoothing = smoothing;
+                    let _ = self.tx.send(UI2VR::UpdateBounds(self.stick_bounds));
+                }
+                Some(noise::Outcome::Close) => self.noise = None,
+                _ if !open => self.noise = None,
+                _ => {}
+            }
+        }
+
+        if let Some(readback) = &mut self.readback {
+            let mut open = true;
+            egui::Window::new("Virtual gamepad").open(&mut open).show(ctx, |ui| readback.show(ui));
+            if !open {
+                self.readback = None;
+            }
+        }
+
         if let Some(failure) = &self.startup_failure {
             self.render_failure(ctx, failure);
         } else if let Some(failure) = &self.runtime_failure {
@@ -0,0 +1,130 @@
+use crate::{
+    mapping::{self, ControllerState, TrackpadDirection},
+    pipe::{ActionReading, InputReadings},
+};
+use eframe::epaint::{Color32, Rounding, Stroke};
+use egui::{FontId, Pos2, Sense, Vec2, pos2, vec2};
+
+/// Where `turns` half-turns clockwise from the top of the trackpad is, `radius` away from `center`.
+fn on_pad(center: Pos2, radius: f32, turns: f32) -> Pos2 {
+    let angle = turns * std::f32::consts::PI;
+    center + vec2(angle.sin(), -angle.cos()) * radius
+}
+
+/// The trackpad with its direction sectors, and where it's being touched.
+#[profiling::function]
+fn trackpad(readings: &InputReadings, ui: &mut egui::Ui) {
+    let (rect, _) = ui.allocate_exact_size(Vec2::splat(ui.spacing().interact_size.y * 6.0), Sense::hover());
+    let painter = ui.painter_at(rect);
+    let center = rect.center();
+    let radius = rect.width() / 2.0 - 2.0;
+    let stroke = ui.visuals().noninteractive().bg_stroke;
+
+    painter.circle(center, radius, Color32::BLACK, stroke);
+    painter.circle_stroke(center, radius * mapping::TRACKPAD_DIRECTION_DISTANCE, stroke);
+    for turns in [mapping::NE, mapping::SE, mapping::SW, mapping::NW] {
+        painter.line_segment(
+            [on_pad(center, radius * mapping::TRACKPAD_DIRECTION_DISTANCE, turns), on_pad(center, radius, turns)],
+            stroke,
+        );
+    }
+
+    let [x, y] = [readings.trackpad_x.value, readings.trackpad_y.value];
+    let clicked = readings.trackpad_click.value;
+    let state = ControllerState { trackpad: [x, y], trackpad_click: clicked, ..Default::default() };
+    let sectors = [
+        (TrackpadDirection::Up, 0.0, "U"),
+        (TrackpadDirection::Right, 0.5, "R"),
+        (TrackpadDirection::Down, 1.0, "D"),
+        (TrackpadDirection::Left, -0.5, "L"),
+    ];
+    for (direction, turns, label) in sectors {
+        let color = if state.trackpad_direction() == Some(direction) { Color32::GOLD } else { Color32::GRAY };
+        painter.text(
+            on_pad(center, radius * 0.7, turns),
+            egui::Align2::CENTER_CENTER,
+            label,
+            FontId::monospace(14.0),
+            color,
+        );
+    }
+
+    // the position is still reported by some runtimes when nothing's touching the pad
+    let touched = readings.trackpad_touch.value || !readings.trackpad_touch.active;
+    let color = match (clicked, touched) {
+        (true, _) => Color32::GOLD,
+        (false, true) => Color32::WHITE,
+        (false, false) => Color32::DARK_GRAY,
+    };
+    painter.circle_filled(center + vec2(x, -y) * radius, 5.0, color);
+}
+
+/// How far the trigger is pulled, with the point where it counts as pressed.
+#[profiling::function]
+fn trigger(readings: &InputReadings, ui: &mut egui::Ui) {
+    let (rect, _) = ui.allocate_exact_size(vec2(ui.spacing().interact_size.y * 6.0, 14.0), Sense::hover());
+    let painter = ui.painter_at(rect);
+    let pulled = readings.trigger.value.clamp(0.0, 1.0);
+    let color = if pulled > mapping::TRIGGER_PRESS { Color32::GOLD } else { Color32::from_rgb(0, 128, 200) };
+
+    painter.rect_filled(rect, Rounding::ZERO, Color32::BLACK);
+    painter.rect_filled(rect.with_max_x(rect.min.x + rect.width() * pulled), Rounding::ZERO, color);
+    let press = rect.min.x + rect.width() * mapping::TRIGGER_PRESS;
+    painter.line_segment([pos2(press, rect.min.y), pos2(press, rect.max.y)], Stroke::new(1.0, Color32::WHITE));
+    painter.rect_stroke(rect, Rounding::ZERO, ui.visuals().noninteractive().bg_stroke);
+}
+
+fn flags<T>(ui: &mut egui::Ui, reading: &ActionReading<T>) {
+    if reading.active {
+        ui.colored_label(Color32::GREEN, "active");
+    } else {
+        ui.colored_label(Color32::RED, "inactive");
+    }
+    if reading.changed {
+        ui.colored_label(Color32::GOLD, "changed");
+    } else {
+        ui.weak("changed");
+    }
+}
+
+fn row<T>(ui: &mut egui::Ui, name: &str, value: String, reading: &ActionReading<T>) {
+    ui.label(name);
+    ui.monospace(value);
+    flags(ui, reading);
+    ui.end_row();
+}
+
+/// Every bound action's raw state and flags.
+#[profiling::function]
+pub fn monitor(readings: &InputReadings, ui: &mut egui::Ui) {
+    if readings.replayed {
+        ui.weak("From the replay, only recorded inputs are shown");
+    }
+    ui.horizontal(|ui| {
+        trackpad(readings, ui);
+        egui::Grid::new("InputMonitor").num_columns(4).show(ui, |ui| {
+            ui.label("Pose");
+            ui.monospace(if readings.pose_tracked { "tracked" } else { "not tracked" });
+            if readings.pose_active {
+                ui.colored_label(Color32::GREEN, "active");
+            } else {
+                ui.colored_label(Color32::RED, "inactive");
+            }
+            ui.end_row();
+
+            ui.label("Trigger");
+            trigger(readings, ui);
+            flags(ui, &readings.trigger);
+            ui.end_row();
+
+            let analog = |reading: &ActionReading<f32>| format!("{:+.3}", reading.value);
+            let digital = |reading: &ActionReading<bool>| if reading.value { "down" } else { "up" }.to_owned();
+            row(ui, "Trackpad X", analog(&readings.trackpad_x), &readings.trackpad_x);
+            row(ui, "Trackpad Y", analog(&readings.trackpad_y), &readings.trackpad_y);
+            row(ui, "Trackpad touch", digital(&readings.trackpad_touch), &readings.trackpad_touch);
+            row(ui, "Trackpad click", digital(&readings.trackpad_click), &readings.trackpad_click);
+            row(ui, "Grip", digital(&readings.grip), &readings.grip);
+            row(ui, "Menu", digital(&readings.menu), &readings.menu);
+        });
+    });
+}
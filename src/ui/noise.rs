@@ -0,0 +1,230 @@
+//! Measures how much the stick jitters while held still, and what deadzone keeps that out.
+
+use crate::{
+    mapping::{Pose, StickFilter, StickPipeline},
+    pipe::VRInputBounds,
+};
+use eframe::epaint::{Color32, Rounding, Stroke};
+use egui::{Align2, FontId, Sense, pos2, vec2};
+use std::time::{Duration, Instant};
+
+/// How long the controller is held still for
+const MEASURE_TIME: Duration = Duration::from_secs(5);
+/// Smoothing settings a deadzone is worked out for, in seconds
+const SMOOTHING_OPTIONS: [f32; 5] = [0.0, 0.01, 0.02, 0.05, 0.1];
+const HISTOGRAM_BINS: usize = 32;
+/// Most recent samples the spectrum is worked out from
+const SPECTRUM_SAMPLES: usize = 512;
+
+/// What the user chose in the window this frame.
+pub enum Outcome {
+    Continue,
+    Apply { deadzone: f32, smoothing: f32 },
+    Close,
+}
+
+pub struct NoiseMeasurement {
+    /// When the first sample arrived
+    started: Option<Instant>,
+    /// Deflection before the deadzone, one for each rotation sample
+    samples: Vec<[f32; 2]>,
+    /// Samples per second, once the measurement is done
+    rate: Option<f32>,
+    /// Worked out once, when the measurement is done
+    spectrum: Vec<(f32, f32)>,
+    /// Share of the noise the deadzone has to cover, in percent
+    percentile: f32,
+    /// Index into `SMOOTHING_OPTIONS` of the row the graphs show
+    selected: usize,
+}
+
+/// The value `percent` of `values` are at or below. `values` has to be sorted.
+fn percentile(values: &[f32], percent: f32) -> f32 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    let index = ((percent / 100.0) * (values.len() - 1) as f32).round() as usize;
+    values[index.min(values.len() - 1)]
+}
+
+/// How far each sample pushes the stick once smoothed, sorted.
+fn magnitudes(samples: &[[f32; 2]], smoothing: f32, rate: f32) -> Vec<f32> {
+    let mut filter = StickFilter::default();
+    let mut magnitudes: Vec<f32> = samples
+        .iter()
+        .map(|sample| {
+            let [x, y] = filter.apply(*sample, smoothing, 1.0 / rate);
+            f32::hypot(x, y)
+        })
+        .collect();
+    magnitudes.sort_by(f32::total_cmp);
+    magnitudes
+}
+
+/// Amplitude of the jitter at each frequency, leaving out where it rests.
+fn spectrum(samples: &[[f32; 2]], rate: f32) -> Vec<(f32, f32)> {
+    let samples = &samples[samples.len().saturating_sub(SPECTRUM_SAMPLES)..];
+    let n = samples.len();
+    if n < 4 {
+        return Vec::new();
+    }
+    let mean = [0, 1].map(|axis| samples.iter().map(|s| s[axis]).sum::<f32>() / n as f32);
+    (1..n / 2)
+        .map(|k| {
+            let power: f32 = [0, 1]
+                .map(|axis| {
+                    let (mut re, mut im) = (0.0, 0.0);
+                    for (i, sample) in samples.iter().enumerate() {
+                        let angle = std::f32::consts::TAU * (k * i) as f32 / n as f32;
+                        re += (sample[axis] - mean[axis]) * angle.cos();
+                        im -= (sample[axis] - mean[axis]) * angle.sin();
+                    }
+                    re * re + im * im
+                })
+                .iter()
+                .sum();
+            (k as f32 * rate / n as f32, 2.0 * power.sqrt() / n as f32)
+        })
+        .collect()
+}
+
+impl NoiseMeasurement {
+    pub fn new() -> Self {
+        Self { started: None, samples: Vec::new(), rate: None, spectrum: Vec::new(), percentile: 99.0, selected: 0 }
+    }
+
+    /// Takes the latest controller pose, from the rotation telemetry.
+    pub fn feed(&mut self, pose: &Pose, pipeline: &StickPipeline) {
+        if self.rate.is_some() {
+            return;
+        }
+        let started = *self.started.get_or_insert_with(Instant::now);
+        self.samples.push(pipeline.deflection(pose));
+        // samples come in bursts each frame, so the rate is taken over the whole measurement
+        let elapsed = started.elapsed();
+        if elapsed >= MEASURE_TIME {
+            let rate = self.samples.len() as f32 / elapsed.as_secs_f32();
+            self.spectrum = spectrum(&self.samples, rate);
+            self.rate = Some(rate);
+        }
+    }
+
+    pub fn show(&mut self, bounds: &VRInputBounds, ui: &mut egui::Ui) -> Outcome {
+        let Some(rate) = self.rate else {
+            ui.heading("Hold the controller still at neutral");
+            let progress =
+                self.started.map_or(0.0, |started| started.elapsed().as_secs_f32() / MEASURE_TIME.as_secs_f32());
+            ui.add(egui::ProgressBar::new(progress.min(1.0)).text(format!("{} samples", self.samples.len())));
+            if self.started.is_none() {
+                ui.weak("Waiting for the controller to be tracked");
+            }
+            return if ui.button("Cancel").clicked() { Outcome::Close } else { Outcome::Continue };
+        };
+
+        let mean = [0, 1].map(|axis| self.samples.iter().map(|s| s[axis]).sum::<f32>() / self.samples.len() as f32);
+        ui.label(format!(
+            "{} samples at {:.0} Hz, resting {:.3} away from neutral",
+            self.samples.len(),
+            rate,
+            f32::hypot(mean[0], mean[1])
+        ))
+        .on_hover_text("Recenter and measure again if it's resting far from neutral");
+        ui.add(egui::Slider::new(&mut self.percentile, 90.0..=100.0).text("% of the noise to cover"));
+
+        let mut outcome = Outcome::Continue;
+        let rows = SMOOTHING_OPTIONS.map(|smoothing| {
+            let magnitudes = magnitudes(&self.samples, smoothing, rate);
+            let deadzone = percentile(&magnitudes, self.percentile);
+            (smoothing, magnitudes, deadzone)
+        });
+        egui::Grid::new("NoiseRecommendations").num_columns(3).show(ui, |ui| {
+            ui.strong("Smoothing");
+            ui.strong("Deadzone");
+            ui.label("");
+            ui.end_row();
+            for (i, (smoothing, _, deadzone)) in rows.iter().enumerate() {
+                let name = if *smoothing > 0.0 { format!("{:.0} ms", smoothing * 1000.0) } else { "None".to_owned() };
+                ui.selectable_value(&mut self.selected, i, name);
+                ui.monospace(format!("{deadzone:.3}"));
+                if ui.button("Apply").clicked() {
+                    outcome = Outcome::Apply { deadzone: *deadzone, smoothing: *smoothing };
+                }
+                ui.end_row();
+            }
+        });
+        ui.label(format!("Now: deadzone {:.3}, smoothing {:.0} ms", bounds.deadzone, bounds.smoothing * 1000.0));
+
+        let (_, magnitudes, deadzone) = &rows[self.selected];
+        ui.label("How far the noise pushes the stick:");
+        histogram(magnitudes, *deadzone, bounds.deadzone, ui);
+        ui.label("Noise by frequency:");
+        spectrum_plot(&self.spectrum, ui);
+
+        ui.horizontal(|ui| {
+            if ui.button("Measure again").clicked() {
+                *self = Self { percentile: self.percentile, ..Self::new() };
+            }
+            if ui.button("Close").clicked() {
+                outcome = Outcome::Close;
+            }
+        });
+        outcome
+    }
+}
+
+/// Counts of `magnitudes` by size, with the recommended and current deadzones marked.
+fn histogram(magnitudes: &[f32], recommended: f32, current: f32, ui: &mut egui::Ui) {
+    let (rect, _) = ui.allocate_exact_size(vec2(ui.spacing().interact_size.y * 16.0, 80.0), Sense::hover());
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, Rounding::ZERO, Color32::BLACK);
+
+    let top = magnitudes.last().copied().unwrap_or(0.0).max(recommended).max(current).max(f32::EPSILON) * 1.1;
+    let mut bins = [0usize; HISTOGRAM_BINS];
+    for magnitude in magnitudes {
+        bins[((magnitude / top * HISTOGRAM_BINS as f32) as usize).min(HISTOGRAM_BINS - 1)] += 1;
+    }
+    let tallest = bins.iter().copied().max().unwrap_or(1).max(1);
+    let width = rect.width() / HISTOGRAM_BINS as f32;
+    for (i, count) in bins.iter().enumerate() {
+        let height = rect.height() * *count as f32 / tallest as f32;
+        let left = rect.min.x + i as f32 * width;
+        painter.rect_filled(
+            egui::Rect::from_min_max(pos2(left + 1.0, rect.max.y - height), pos2(left + width, rect.max.y)),
+            Rounding::ZERO,
+            Color32::GRAY,
+        );
+    }
+
+    let marks = [(current, Color32::from_rgb(0, 128, 200)), (recommended, Color32::GOLD)];
+    for (value, color) in marks {
+        let x = rect.min.x + rect.width() * value / top;
+        painter.line_segment([pos2(x, rect.min.y), pos2(x, rect.max.y)], Stroke::new(2.0, color));
+    }
+    painter.text(rect.right_top(), Align2::RIGHT_TOP, format!("{top:.3}"), FontId::monospace(10.0), Color32::WHITE);
+    painter.rect_stroke(rect, Rounding::ZERO, ui.visuals().noninteractive().bg_stroke);
+}
+
+fn spectrum_plot(spectrum: &[(f32, f32)], ui: &mut egui::Ui) {
+    let (rect, _) = ui.allocate_exact_size(vec2(ui.spacing().interact_size.y * 16.0, 80.0), Sense::hover());
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, Rounding::ZERO, Color32::BLACK);
+
+    let highest = spectrum.last().map_or(1.0, |(frequency, _)| *frequency);
+    let loudest = spectrum.iter().map(|(_, amplitude)| *amplitude).fold(f32::EPSILON, f32::max);
+    let points = spectrum
+        .iter()
+        .map(|(frequency, amplitude)| {
+            pos2(rect.min.x + rect.width() * frequency / highest, rect.max.y - rect.height() * amplitude / loudest)
+        })
+        .collect();
+    painter.add(egui::Shape::line(points, Stroke::new(1.0, Color32::GREEN)));
+    painter.text(
+        rect.right_bottom(),
+        Align2::RIGHT_BOTTOM,
+        format!("{highest:.0} Hz"),
+        FontId::monospace(10.0),
+        Color32::WHITE,
+    );
+    painter.text(rect.left_top(), Align2::LEFT_TOP, format!("{loudest:.4}"), FontId::monospace(10.0), Color32::WHITE);
+    painter.rect_stroke(rect, Rounding::ZERO, ui.visuals().noninteractive().bg_stroke);
+}
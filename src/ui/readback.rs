@@ -0,0 +1,312 @@
+//! Reads the virtual gamepad back the way a game would, to show it exists and what it reports.
+
+use eframe::epaint::Color32;
+use evdev::{AbsoluteAxisCode, Device, EventSummary, InputEvent, KeyCode};
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{self, Read},
+    os::{fd::AsRawFd, unix::fs::OpenOptionsExt},
+    path::{Path, PathBuf},
+};
+
+/// Events kept in the log
+const LOG_LENGTH: usize = 200;
+
+// joydev ioctls, from linux/joystick.h
+const JSIOCGAXES: libc::c_ulong = 0x8001_6a11;
+const JSIOCGBUTTONS: libc::c_ulong = 0x8001_6a12;
+const JSIOCGAXMAP: libc::c_ulong = 0x8040_6a32;
+const JSIOCGBTNMAP: libc::c_ulong = 0x8400_6a34;
+const JS_EVENT_BUTTON: u8 = 0x01;
+const JS_EVENT_AXIS: u8 = 0x02;
+const JS_EVENT_INIT: u8 = 0x80;
+
+/// Opens `path` for reading only, without blocking on reads.
+fn open_read_only(path: &Path) -> io::Result<File> {
+    std::fs::OpenOptions::new().read(true).custom_flags(libc::O_NONBLOCK).open(path)
+}
+
+/// The device nodes under `syspath` whose names start with `prefix`, like `event` or `js`.
+fn nodes(syspath: &Path, prefix: &str) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(syspath) else { return Vec::new() };
+    let mut nodes: Vec<PathBuf> = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.file_name())
+        .filter(|name| name.to_string_lossy().starts_with(prefix))
+        .map(|name| Path::new("/dev/input").join(name))
+        .collect();
+    nodes.sort();
+    nodes
+}
+
+/// `/dev/input/js*`, as the older joystick interface some games still use sees the gamepad.
+struct Joydev {
+    path: PathBuf,
+    file: File,
+    /// The evdev axis behind each joystick axis
+    axis_map: Vec<AbsoluteAxisCode>,
+    /// The evdev key behind each joystick button
+    button_map: Vec<KeyCode>,
+    axes: Vec<i16>,
+    buttons: Vec<bool>,
+}
+
+impl Joydev {
+    fn open(path: PathBuf) -> io::Result<Self> {
+        let file = open_read_only(&path)?;
+        let fd = file.as_raw_fd();
+        let (mut axes, mut buttons) = (0u8, 0u8);
+        let mut axis_map = [0u8; 0x40];
+        let mut button_map = [0u16; 0x200];
+        // SAFETY: each buffer is the size its ioctl writes
+        unsafe {
+            for (request, buffer) in [
+                (JSIOCGAXES, &mut axes as *mut u8 as *mut libc::c_void),
+                (JSIOCGBUTTONS, &mut buttons as *mut u8 as *mut libc::c_void),
+                (JSIOCGAXMAP, axis_map.as_mut_ptr() as *mut libc::c_void),
+                (JSIOCGBTNMAP, button_map.as_mut_ptr() as *mut libc::c_void),
+            ] {
+                if libc::ioctl(fd, request, buffer) < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+        }
+        Ok(Self {
+            path,
+            file,
+            axis_map: axis_map[..axes as usize].iter().map(|code| AbsoluteAxisCode(*code as u16)).collect(),
+            button_map: button_map[..buttons as usize].iter().map(|code| KeyCode(*code)).collect(),
+            axes: vec![0; axes as usize],
+            buttons: vec![false; buttons as usize],
+        })
+    }
+
+    /// Reads every waiting `js_event`.
+    fn poll(&mut self) -> io::Result<()> {
+        let mut event = [0u8; 8];
+        loop {
+            match self.file.read_exact(&mut event) {
+                Ok(()) => {}
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(err) => return Err(err),
+            }
+            // u32 time, i16 value, u8 type, u8 number
+            let value = i16::from_ne_bytes([event[4], event[5]]);
+            let number = event[7] as usize;
+            match event[6] & !JS_EVENT_INIT {
+                JS_EVENT_AXIS if number < self.axes.len() => self.axes[number] = value,
+                JS_EVENT_BUTTON if number < self.buttons.len() => self.buttons[number] = value != 0,
+                _ => {}
+            }
+        }
+    }
+}
+
+pub struct Readback {
+    /// The gamepad's input device under /sys
+    syspath: PathBuf,
+    device: Option<(PathBuf, Device)>,
+    joydev: Option<Joydev>,
+    /// Why something couldn't be opened or read
+    errors: Vec<String>,
+    /// Something couldn't be opened for lack of permission
+    denied: bool,
+    /// The device has a joystick interface node, whether or not it opened
+    has_joydev: bool,
+    log: VecDeque<String>,
+    paused: bool,
+}
+
+fn describe(event: &InputEvent) -> String {
+    match event.destructure() {
+        EventSummary::AbsoluteAxis(_, code, value) => format!("{code:?} {value}"),
+        EventSummary::Key(_, code, value) => format!("{code:?} {value}"),
+        EventSummary::Synchronization(_, code, value) => format!("{code:?} {value}"),
+        _ => format!("{:?} {} {}", event.event_type(), event.code(), event.value()),
+    }
+}
+
+impl Readback {
+    /// Finds and opens the nodes of the device at `syspath`.
+    pub fn open(syspath: PathBuf) -> Self {
+        let mut readback = Self {
+            syspath,
+            device: None,
+            joydev: None,
+            errors: Vec::new(),
+            denied: false,
+            has_joydev: false,
+            log: VecDeque::new(),
+            paused: false,
+        };
+        readback.reopen();
+        readback
+    }
+
+    fn reopen(&mut self) {
+        self.errors.clear();
+        self.denied = false;
+        self.device = None;
+        self.joydev = None;
+
+        match nodes(&self.syspath, "event").into_iter().next() {
+            Some(path) => match open_read_only(&path).and_then(|file| Device::from_fd(file.into())) {
+                Ok(device) => self.device = Some((path, device)),
+                Err(err) => self.failed("open", &path, err),
+            },
+            None => self.errors.push(format!("No event node under {}", self.syspath.display())),
+        }
+        // joydev is only there when its module is loaded
+        let joydev = nodes(&self.syspath, "js").into_iter().next();
+        self.has_joydev = joydev.is_some();
+        if let Some(path) = joydev {
+            match Joydev::open(path.clone()) {
+                Ok(joydev) => self.joydev = Some(joydev),
+                Err(err) => self.failed("open", &path, err),
+            }
+        }
+    }
+
+    fn failed(&mut self, doing: &str, path: &Path, err: io::Error) {
+        self.denied |= err.kind() == io::ErrorKind::PermissionDenied;
+        self.errors.push(format!("Couldn't {} {}: {}", doing, path.display(), err));
+    }
+
+    fn poll(&mut self) {
+        if let Some((path, device)) = &mut self.device {
+            let failure = match device.fetch_events() {
+                Ok(events) => {
+                    for event in events {
+                        if !self.paused {
+                            self.log.push_back(describe(&event));
+                        }
+                    }
+                    None
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => None,
+                Err(err) => Some((path.clone(), err)),
+            };
+            while self.log.len() > LOG_LENGTH {
+                self.log.pop_front();
+            }
+            if let Some((path, err)) = failure {
+                self.failed("read", &path, err);
+                self.device = None;
+            }
+        }
+        if let Some(joydev) = &mut self.joydev
+            && let Err(err) = joydev.poll()
+        {
+            let path = joydev.path.clone();
+            self.failed("read", &path, err);
+            self.joydev = None;
+        }
+    }
+
+    #[profiling::function]
+    pub fn show(&mut self, ui: &mut egui::Ui) {
+        self.poll();
+
+        ui.horizontal(|ui| {
+            ui.label(format!("Device: {}", self.syspath.display()));
+            if ui.button("Reopen").clicked() {
+                self.reopen();
+            }
+        });
+        for error in &self.errors {
+            ui.colored_label(Color32::RED, error);
+        }
+        if self.denied {
+            ui.weak("Reading input devices usually takes being in the `input` group");
+        }
+
+        if let Some((path, device)) = &self.device {
+            let id = device.input_id();
+            ui.label(format!(
+                "{} is \"{}\", bus {:#06x}, vendor {:#06x}, product {:#06x}, version {:#06x}",
+                path.display(),
+                device.name().unwrap_or("unnamed"),
+                id.bus_type().0,
+                id.vendor(),
+                id.product(),
+                id.version()
+            ));
+
+            ui.strong("Axes, as games see them");
+            let state = device.cached_state().abs_vals();
+            egui::Grid::new("ReadbackAxes").num_columns(7).striped(true).show(ui, |ui| {
+                for heading in ["Axis", "Value", "Minimum", "Maximum", "Fuzz", "Flat", "Resolution"] {
+                    ui.strong(heading);
+                }
+                ui.end_row();
+                for axis in device.supported_absolute_axes().iter().flat_map(|axes| axes.iter()) {
+                    let Some(info) = state.and_then(|state| state.get(axis.0 as usize)) else { continue };
+                    ui.monospace(format!("{axis:?}"));
+                    let span = (info.maximum - info.minimum).max(1) as f32;
+                    ui.add(
+                        egui::ProgressBar::new((info.value - info.minimum) as f32 / span)
+                            .text(info.value.to_string())
+                            .desired_width(120.0),
+                    );
+                    for value in [info.minimum, info.maximum, info.fuzz, info.flat, info.resolution] {
+                        ui.monospace(value.to_string());
+                    }
+                    ui.end_row();
+                }
+            });
+
+            ui.strong("Buttons");
+            let pressed = device.cached_state().key_vals();
+            ui.horizontal_wrapped(|ui| {
+                for key in device.supported_keys().iter().flat_map(|keys| keys.iter()) {
+                    let name = format!("{key:?}");
+                    if pressed.is_some_and(|pressed| pressed.contains(key)) {
+                        ui.colored_label(Color32::BLACK, egui::RichText::new(name).background_color(Color32::GOLD));
+                    } else {
+                        ui.weak(name);
+                    }
+                }
+            });
+            let effects: Vec<String> =
+                device.supported_ff().iter().flat_map(|ff| ff.iter()).map(|effect| format!("{effect:?}")).collect();
+            ui.label(format!(
+                "Force feedback: {}",
+                if effects.is_empty() { "none".to_owned() } else { effects.join(", ") }
+            ));
+        }
+
+        if let Some(joydev) = &self.joydev {
+            ui.strong(format!("Joystick interface, {}", joydev.path.display()));
+            egui::Grid::new("ReadbackJoydev").num_columns(3).striped(true).show(ui, |ui| {
+                for (i, (code, value)) in joydev.axis_map.iter().zip(&joydev.axes).enumerate() {
+                    ui.monospace(format!("Axis {i}"));
+                    ui.monospace(format!("{code:?}"));
+                    ui.monospace(value.to_string());
+                    ui.end_row();
+                }
+                for (i, (code, pressed)) in joydev.button_map.iter().zip(&joydev.buttons).enumerate() {
+                    ui.monospace(format!("Button {i}"));
+                    ui.monospace(format!("{code:?}"));
+                    ui.monospace(if *pressed { "down" } else { "up" });
+                    ui.end_row();
+                }
+            });
+        } else if !self.has_joydev {
+            ui.weak("No joystick interface, the joydev module isn't loaded");
+        }
+
+        ui.horizontal(|ui| {
+            ui.strong("Events");
+            ui.checkbox(&mut self.paused, "Pause");
+            if ui.button("Clear").clicked() {
+                self.log.clear();
+            }
+        });
+        egui::ScrollArea::vertical().max_height(200.0).stick_to_bottom(true).show(ui, |ui| {
+            for line in &self.log {
+                ui.monospace(line);
+            }
+        });
+    }
+}
@@ -6,13 +6,7 @@ pub struct SimpleLogger;
 pub static LOGGER: SimpleLogger = SimpleLogger;
 
 impl log::Log for SimpleLogger {
-    fn enabled(&self, metadata: &Metadata) -> bool {
-        let enable_debug = if let Ok(x) = env::var("FLIGHTWAND_LOG_LEVEL") { x == "debug" } else { false };
-
-        let log_level = if enable_debug { Level::Debug } else { Level::Info };
-
-        metadata.level() <= log_level
-    }
+    fn enabled(&self, metadata: &Metadata) -> bool { metadata.level() <= log::max_level() }
 
     fn log(&self, record: &Record) {
         if self.enabled(record.metadata()) {
@@ -49,4 +43,11 @@ impl log::Log for SimpleLogger {
     fn flush(&self) {}
 }
 
-pub fn init_logger() { log::set_logger(&LOGGER).map(|()| log::set_max_level(LevelFilter::Trace)).ok(); }
+/// Installs the logger. `FLIGHTWAND_LOG_LEVEL=debug` raises `level` to at least debug.
+pub fn init_logger(level: LevelFilter) {
+    let enable_debug = if let Ok(x) = env::var("FLIGHTWAND_LOG_LEVEL") { x == "debug" } else { false };
+
+    let level = if enable_debug { level.max(LevelFilter::Debug) } else { level };
+
+    log::set_logger(&LOGGER).map(|()| log::set_max_level(level)).ok();
+}
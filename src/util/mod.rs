@@ -1,22 +1,55 @@
-use crate::pipe::VRInputBounds;
+use std::{env, path::PathBuf};
 
 pub mod logger;
+pub mod runtime;
 
+pub fn home() -> Option<PathBuf> { env::var_os("HOME").map(PathBuf::from) }
+
+/// `var` as a path, or `fallback` under the home directory.
+pub fn xdg_home(var: &str, fallback: &str) -> Option<PathBuf> {
+    env::var_os(var).filter(|dir| !dir.is_empty()).map(PathBuf::from).or_else(|| Some(home()?.join(fallback)))
+}
+
+/// The "up" vector that makes `orientation` the neutral stick position,
+/// i.e. the inverse of [`crate::mapping::modifier`] for a vertical output.
+#[profiling::function]
+pub fn capture_up(orientation: &[f32; 4]) -> [f32; 3] {
+    let up = nalgebra::Quaternion::new(0.0, 0.0, 1.0, 0.0);
+    let quat = nalgebra::Quaternion::new(orientation[3], orientation[0], orientation[1], orientation[2]);
+    let res = quat.conjugate() * up * quat;
+    let res = res.coords.normalize();
+
+    [res.x, res.y, res.z]
+}
+
+/// Heading of `orientation` around the vertical axis, in radians.
+#[profiling::function]
+pub fn yaw(orientation: &[f32; 4]) -> f32 {
+    let quat = nalgebra::UnitQuaternion::from_quaternion(nalgebra::Quaternion::new(
+        orientation[3],
+        orientation[0],
+        orientation[1],
+        orientation[2],
+    ));
+    let forward = quat * nalgebra::Vector3::new(0.0, 0.0, -1.0);
+    f32::atan2(-forward.x, -forward.z)
+}
+
+/// `orientation` as seen from a frame turned `yaw` radians around the vertical axis.
 #[profiling::function]
-pub fn modifier(input: &[f32; 4], up: [f32; 3]) -> [f32; 4] {
-    let id = nalgebra::Quaternion::new(0.0, up[0], up[1], up[2]).normalize();
-    let quat = nalgebra::Quaternion::new(input[3], input[0], input[1], input[2]);
-    let res = quat * id * quat.conjugate();
-    let res = res.coords.normalize().clone_owned();
-
-    // we don't use the fourth coordinate for anything this function touches,
-    // set it to something out of the graph's bounds
-    [res.x, res.y, res.z, -2.0]
+pub fn remove_yaw(orientation: &[f32; 4], yaw: f32) -> [f32; 4] {
+    let turn = nalgebra::UnitQuaternion::from_axis_angle(&nalgebra::Vector3::y_axis(), yaw);
+    let quat = nalgebra::Quaternion::new(orientation[3], orientation[0], orientation[1], orientation[2]);
+    let res = turn.inverse().into_inner() * quat;
+
+    [res.i, res.j, res.k, res.w]
 }
 
+/// `vector` turned `yaw` radians around the vertical axis.
 #[profiling::function]
-pub fn rot_to_joy(input: &[f32; 2], _bounds: VRInputBounds) -> [f32; 2] {
-    let x = f32::sin(input[0]);
-    let y = f32::sin(-input[1]);
-    [x, y]
+pub fn rotate_yaw(vector: [f32; 3], yaw: f32) -> [f32; 3] {
+    let turn = nalgebra::UnitQuaternion::from_axis_angle(&nalgebra::Vector3::y_axis(), yaw);
+    let res = turn * nalgebra::Vector3::from(vector);
+
+    [res.x, res.y, res.z]
 }
@@ -0,0 +1,134 @@
+use crate::util::{home, xdg_home};
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+/// An OpenXR runtime found through its manifest.
+#[derive(Clone, PartialEq)]
+pub struct Runtime {
+    pub name: String,
+    /// The manifest, with symlinks resolved
+    pub manifest: PathBuf,
+    /// The runtime library, either absolute or a bare name for the dynamic loader to find
+    pub library: PathBuf,
+    /// Whether this is the runtime the OpenXR loader would pick on its own
+    pub active: bool,
+}
+
+impl Runtime {
+    /// Reads a runtime manifest. `manifest` should already have its symlinks resolved.
+    pub fn from_manifest(manifest: &Path) -> Option<Runtime> {
+        let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(manifest).ok()?).ok()?;
+        let runtime = json.get("runtime")?;
+        let library = Path::new(runtime.get("library_path")?.as_str()?);
+
+        // relative library paths are relative to the manifest, bare names are left to the loader
+        let library = if library.components().count() > 1 && library.is_relative() {
+            manifest.parent()?.join(library)
+        } else {
+            library.to_owned()
+        };
+        let name = match runtime.get("name").and_then(|name| name.as_str()) {
+            Some(name) => name.to_owned(),
+            None => manifest.file_stem()?.to_string_lossy().into_owned(),
+        };
+
+        Some(Runtime { name, manifest: manifest.to_owned(), library, active: false })
+    }
+}
+
+/// `var` as a list of paths, or `fallback`.
+fn xdg_dirs(var: &str, fallback: &str) -> Vec<PathBuf> {
+    let dirs = env::var(var).ok().filter(|dirs| !dirs.is_empty()).unwrap_or_else(|| fallback.to_owned());
+    dirs.split(':').filter(|dir| !dir.is_empty()).map(PathBuf::from).collect()
+}
+
+/// Directories holding `active_runtime.json`, in the order the loader checks them.
+fn config_dirs() -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = xdg_home("XDG_CONFIG_HOME", ".config").into_iter().collect();
+    dirs.extend(xdg_dirs("XDG_CONFIG_DIRS", "/etc/xdg"));
+    dirs.push(PathBuf::from("/etc"));
+    dirs.into_iter().map(|dir| dir.join("openxr/1")).collect()
+}
+
+/// Directories runtimes commonly install their manifests to.
+fn data_dirs() -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = xdg_home("XDG_DATA_HOME", ".local/share").into_iter().collect();
+    dirs.extend(xdg_dirs("XDG_DATA_DIRS", "/usr/local/share:/usr/share"));
+    dirs.into_iter().map(|dir| dir.join("openxr/1")).collect()
+}
+
+/// Manifests installed outside of the standard locations.
+fn known_manifests() -> Vec<PathBuf> {
+    let Some(home) = home() else { return Vec::new() };
+    ["steam/steamapps", ".steam/steam/steamapps", ".local/share/Steam/steamapps"]
+        .into_iter()
+        .map(|library| home.join(library).join("common/SteamVR/steamxr_linux64.json"))
+        .collect()
+}
+
+/// The manifest the OpenXR loader would use without being told otherwise.
+fn active_manifest() -> Option<PathBuf> {
+    if let Some(manifest) = env::var_os("XR_RUNTIME_JSON").filter(|manifest| !manifest.is_empty()) {
+        return Some(PathBuf::from(manifest));
+    }
+    config_dirs().into_iter().map(|dir| dir.join("active_runtime.json")).find(|manifest| manifest.is_file())
+}
+
+/// Finds every OpenXR runtime manifest in the standard `openxr/1` locations, plus a few well known
+/// install paths. The active runtime comes first.
+pub fn discover() -> Vec<Runtime> {
+    let mut candidates: Vec<PathBuf> = active_manifest().into_iter().collect();
+    for dir in config_dirs().into_iter().chain(data_dirs()) {
+        let Ok(entries) = fs::read_dir(dir) else { continue };
+        let mut manifests: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect();
+        manifests.sort();
+        candidates.extend(manifests);
+    }
+    candidates.extend(known_manifests());
+
+    let active = active_manifest().and_then(|manifest| manifest.canonicalize().ok());
+    let mut runtimes: Vec<Runtime> = Vec::new();
+    for candidate in candidates {
+        // active_runtime.json is usually a symlink to one of the others
+        let Ok(manifest) = candidate.canonicalize() else { continue };
+        if runtimes.iter().any(|runtime| runtime.manifest == manifest) {
+            continue;
+        }
+        if let Some(mut runtime) = Runtime::from_manifest(&manifest) {
+            runtime.active = active.as_ref() == Some(&manifest);
+            runtimes.push(runtime);
+        }
+    }
+    runtimes
+}
+
+fn remembered_path() -> Option<PathBuf> { Some(xdg_home("XDG_CONFIG_HOME", ".config")?.join("FlightWand/runtime")) }
+
+/// The manifest picked last time, if any.
+pub fn remembered() -> Option<PathBuf> {
+    let manifest = fs::read_to_string(remembered_path()?).ok()?;
+    let manifest = manifest.trim();
+    (!manifest.is_empty()).then(|| PathBuf::from(manifest))
+}
+
+/// Remembers the picked manifest for next time. `None` goes back to the active runtime.
+pub fn remember(manifest: Option<&Path>) -> io::Result<()> {
+    let path = remembered_path().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no home directory"))?;
+    match manifest {
+        Some(manifest) => {
+            let dir =
+                path.parent().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no parent directory"))?;
+            fs::create_dir_all(dir)?;
+            fs::write(&path, manifest.as_os_str().as_encoded_bytes())
+        }
+        None => match fs::remove_file(&path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        },
+    }
+}
@@ -0,0 +1,40 @@
+use crate::mapping::ControllerState;
+use crate::pipe::{ControllerButton, EngageSettings, ReleaseBehavior};
+
+/// Tracks whether the stick is "grabbed", and what to output while it isn't.
+pub struct Clutch {
+    pub settings: EngageSettings,
+    engaged: bool,
+    held: [f32; 2],
+}
+
+impl Clutch {
+    pub fn new(settings: EngageSettings) -> Self { Self { settings, engaged: true, held: [0.0; 2] } }
+
+    /// Reads the engage button. Returns the new engagement state if it changed this frame.
+    pub fn update(&mut self, input: &ControllerState) -> Option<bool> {
+        let engaged = self.settings.button == ControllerButton::None || input.pressed(self.settings.button);
+        if engaged == self.engaged {
+            return None;
+        }
+
+        self.engaged = engaged;
+        Some(engaged)
+    }
+
+    /// The axis values to emit, given the live output and the trim center.
+    pub fn output(&mut self, live: [f32; 2], center: [f32; 2]) -> [f32; 2] {
+        if self.engaged {
+            self.held = live;
+            return live;
+        }
+
+        match self.settings.release {
+            ReleaseBehavior::Recenter => center,
+            ReleaseBehavior::Freeze | ReleaseBehavior::HoldLast => self.held,
+        }
+    }
+
+    /// Whether button output should stop updating.
+    pub fn buttons_frozen(&self) -> bool { !self.engaged && self.settings.release == ReleaseBehavior::Freeze }
+}
@@ -0,0 +1,51 @@
+use crate::pipe::{FailsafeBehavior, FailsafeSettings};
+use std::time::Instant;
+
+/// Watches controller tracking, and decides what the axes do while it's lost.
+pub struct Failsafe {
+    pub settings: FailsafeSettings,
+    /// When tracking was lost, `None` while tracked
+    lost_since: Option<Instant>,
+    last: [f32; 2],
+    losses: u32,
+}
+
+impl Failsafe {
+    pub fn new(settings: FailsafeSettings) -> Self { Self { settings, lost_since: None, last: [0.0; 2], losses: 0 } }
+
+    /// Returns the new tracking state and the number of losses so far, if tracking changed.
+    pub fn update(&mut self, tracked: bool) -> Option<(bool, u32)> {
+        if tracked == self.tracked() {
+            return None;
+        }
+
+        if tracked {
+            self.lost_since = None;
+        } else {
+            self.lost_since = Some(Instant::now());
+            self.losses += 1;
+        }
+        Some((tracked, self.losses))
+    }
+
+    pub fn tracked(&self) -> bool { self.lost_since.is_none() }
+
+    /// The axis values to emit, given the live output and the trim center.
+    pub fn output(&mut self, live: [f32; 2], center: [f32; 2]) -> [f32; 2] {
+        let Some(lost_since) = self.lost_since else {
+            self.last = live;
+            return live;
+        };
+
+        match self.settings.behavior {
+            FailsafeBehavior::Center => center,
+            FailsafeBehavior::HoldLast => self.last,
+            FailsafeBehavior::Decay => {
+                let elapsed = lost_since.elapsed().as_secs_f32() * 1000.0;
+                let remaining = if self.settings.decay > 0.0 { 1.0 - elapsed / self.settings.decay } else { 0.0 };
+                let remaining = remaining.clamp(0.0, 1.0);
+                [0, 1].map(|i| center[i] + (self.last[i] - center[i]) * remaining)
+            }
+        }
+    }
+}
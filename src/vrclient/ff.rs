@@ -0,0 +1,119 @@
+use crate::pipe::ForceFeedbackSettings;
+use evdev::uinput::VirtualDevice;
+use evdev::{EventSummary, FFEffectCode, FFEffectData, FFEffectKind, InputEvent, UInputCode};
+use std::{collections::HashMap, io, time::Instant};
+
+/// Number of effects a game can upload at once
+pub const FF_EFFECTS_MAX: u32 = 16;
+
+/// A game asked for an effect to be played `count` times.
+struct Playback {
+    started: Instant,
+    count: i32,
+}
+
+/// Keeps track of the force feedback effects games upload to the virtual device, and how strongly
+/// they should be shaking the controller right now.
+pub struct ForceFeedback {
+    pub settings: ForceFeedbackSettings,
+    effects: HashMap<i16, FFEffectData>,
+    playing: HashMap<i16, Playback>,
+    /// Set by the game through `FF_GAIN`, `0.0..=1.0`
+    device_gain: f32,
+}
+
+impl ForceFeedback {
+    pub fn new(settings: ForceFeedbackSettings) -> Self {
+        Self { settings, effects: HashMap::new(), playing: HashMap::new(), device_gain: 1.0 }
+    }
+
+    /// Handles every pending upload/erase/play request. The device must be non-blocking.
+    pub fn poll(&mut self, device: &mut VirtualDevice) -> io::Result<()> {
+        let events: Vec<InputEvent> = match device.fetch_events() {
+            Ok(events) => events.collect(),
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+            Err(err) => return Err(err),
+        };
+
+        for event in events {
+            match event.destructure() {
+                EventSummary::UInput(event, UInputCode::UI_FF_UPLOAD, _) => {
+                    // the upload is finished (and the game unblocked) when this is dropped
+                    let mut upload = device.process_ff_upload(event)?;
+                    self.effects.insert(upload.effect_id(), upload.effect());
+                    upload.set_retval(0);
+                }
+                EventSummary::UInput(event, UInputCode::UI_FF_ERASE, _) => {
+                    let erase = device.process_ff_erase(event)?;
+                    let id = erase.effect_id() as i16;
+                    self.effects.remove(&id);
+                    self.playing.remove(&id);
+                }
+                EventSummary::ForceFeedback(_, FFEffectCode::FF_GAIN, value) => {
+                    self.device_gain = value as f32 / u16::MAX as f32;
+                }
+                EventSummary::ForceFeedback(_, code, value) => {
+                    let id = code.0 as i16;
+                    if value > 0 {
+                        self.playing.insert(id, Playback { started: Instant::now(), count: value });
+                    } else {
+                        self.playing.remove(&id);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// How strongly the controller should be vibrating, `0.0..=1.0`. Finished effects are forgotten.
+    pub fn amplitude(&mut self) -> f32 {
+        if !self.settings.enabled {
+            return 0.0;
+        }
+
+        let now = Instant::now();
+        let effects = &self.effects;
+        let mut strongest: f32 = 0.0;
+
+        self.playing.retain(|id, playback| {
+            let Some(effect) = effects.get(id) else { return false };
+
+            let elapsed = now.duration_since(playback.started).as_millis();
+            let delay = effect.replay.delay as u128;
+            let length = effect.replay.length as u128;
+
+            // a length of zero plays forever
+            let active = if length == 0 {
+                elapsed >= delay
+            } else {
+                let period = delay + length;
+                if elapsed / period >= playback.count as u128 {
+                    return false;
+                }
+                elapsed % period >= delay
+            };
+
+            if active {
+                strongest = strongest.max(strength(&effect.kind));
+            }
+            true
+        });
+
+        (strongest * self.device_gain * self.settings.gain).clamp(0.0, 1.0)
+    }
+}
+
+/// Normalized strength of an effect, ignoring envelopes.
+fn strength(kind: &FFEffectKind) -> f32 {
+    match kind {
+        FFEffectKind::Rumble { strong_magnitude, weak_magnitude } => {
+            // the wand only has one motor, so the light one counts for less
+            f32::max(*strong_magnitude as f32, *weak_magnitude as f32 * 0.5) / u16::MAX as f32
+        }
+        FFEffectKind::Constant { level, .. } => level.unsigned_abs() as f32 / i16::MAX as f32,
+        FFEffectKind::Periodic { magnitude, .. } => magnitude.unsigned_abs() as f32 / i16::MAX as f32,
+        _ => 0.0,
+    }
+}
@@ -0,0 +1,58 @@
+use crate::{
+    mapping::GamepadState,
+    pipe::{HapticEvent, HapticPulse, HapticSettings, VRInputBounds},
+};
+use openxr as xr;
+
+/// Converts a pulse from the settings into something OpenXR can play.
+pub fn vibration(pulse: &HapticPulse) -> xr::HapticVibration<'static> {
+    xr::HapticVibration::new()
+        .amplitude(pulse.amplitude.clamp(0.0, 1.0))
+        .duration(xr::Duration::from_nanos((pulse.duration * 1_000_000.0) as i64))
+        .frequency(pulse.frequency)
+}
+
+/// Watches the stick and trigger for the threshold crossings that have haptic events.
+pub struct HapticTriggers {
+    pub settings: HapticSettings,
+    outside_deadzone: bool,
+    saturated: bool,
+    past_detent: bool,
+}
+
+impl HapticTriggers {
+    pub fn new(settings: HapticSettings) -> Self {
+        Self { settings, outside_deadzone: false, saturated: false, past_detent: false }
+    }
+
+    /// `stick` is this frame's mapping, with its deflection compared against the deadzone in `bounds`.
+    pub fn update(&mut self, stick: &GamepadState, bounds: &VRInputBounds, trigger: f32) -> Vec<HapticEvent> {
+        let mut events = Vec::new();
+
+        let outside_deadzone = stick.deflection >= bounds.deadzone;
+        if outside_deadzone && !self.outside_deadzone {
+            events.push(HapticEvent::DeadzoneExit);
+        }
+        self.outside_deadzone = outside_deadzone;
+
+        let saturated = stick.saturated;
+        if saturated && !self.saturated {
+            events.push(HapticEvent::Saturation);
+        }
+        self.saturated = saturated;
+
+        // the detent is felt going either way
+        let past_detent = trigger >= self.settings.throttle_detent;
+        if past_detent != self.past_detent {
+            events.push(HapticEvent::ThrottleDetent);
+        }
+        self.past_detent = past_detent;
+
+        events
+    }
+
+    /// The pulse to play for `event`, if it's enabled.
+    pub fn pulse(&self, event: HapticEvent) -> Option<&HapticPulse> {
+        Some(self.settings.pulse(event)).filter(|pulse| pulse.enabled)
+    }
+}
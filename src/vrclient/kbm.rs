@@ -0,0 +1,132 @@
+use crate::mapping::ControllerState;
+use crate::pipe::{ControllerButton, KeyboardMouseSettings, KeyboardMouseStick};
+use evdev::uinput::VirtualDevice;
+use evdev::{AttributeSet, InputEvent, KeyCode, RelativeAxisCode};
+use std::{collections::BTreeSet, io, time::Instant};
+
+/// Highest regular keyboard key, everything from `KEY_ESC` to here is registered on the device
+const LAST_KEY: u16 = KeyCode::KEY_MICMUTE.0;
+
+#[profiling::function]
+fn bind_keyboard_mouse() -> io::Result<VirtualDevice> {
+    let mut keys = AttributeSet::<KeyCode>::new();
+    for code in KeyCode::KEY_ESC.0..=LAST_KEY {
+        keys.insert(KeyCode(code));
+    }
+    keys.insert(KeyCode::BTN_LEFT);
+    keys.insert(KeyCode::BTN_RIGHT);
+    keys.insert(KeyCode::BTN_MIDDLE);
+    keys.insert(KeyCode::BTN_SIDE);
+    keys.insert(KeyCode::BTN_EXTRA);
+
+    let mut axes = AttributeSet::<RelativeAxisCode>::new();
+    axes.insert(RelativeAxisCode::REL_X);
+    axes.insert(RelativeAxisCode::REL_Y);
+    axes.insert(RelativeAxisCode::REL_WHEEL);
+
+    VirtualDevice::builder()?
+        .name("FlightWand Virtual Keyboard/Mouse")
+        .with_keys(&keys)?
+        .with_relative_axes(&axes)?
+        .build()
+}
+
+/// Output sink that turns the stick into mouse motion or key pulses, and buttons into key combos.
+pub struct KeyboardMouse {
+    pub settings: KeyboardMouseSettings,
+    /// Only created once the sink is first enabled
+    device: Option<VirtualDevice>,
+    pressed: BTreeSet<KeyCode>,
+    /// Sub-count mouse motion carried over to the next tick
+    remainder: [f32; 2],
+    started: Instant,
+}
+
+impl KeyboardMouse {
+    pub fn new(settings: KeyboardMouseSettings) -> Self {
+        Self { settings, device: None, pressed: BTreeSet::new(), remainder: [0.0; 2], started: Instant::now() }
+    }
+
+    /// Lets go of every key and mouse button that's held down.
+    pub fn release(&mut self) -> io::Result<()> {
+        if let Some(device) = &mut self.device
+            && !self.pressed.is_empty()
+        {
+            let events: Vec<InputEvent> = self.pressed.iter().map(|key| InputEvent::new(1, key.0, 0)).collect();
+            device.emit(&events)?;
+            self.pressed.clear();
+        }
+        Ok(())
+    }
+
+    /// Sends one tick of output. `axes` is the final stick output in `-1.0..=1.0`, `dt` is the tick
+    /// length in seconds, and buttons for which `claimed` returns true are skipped.
+    pub fn update(
+        &mut self,
+        axes: [f32; 2],
+        input: &ControllerState,
+        claimed: impl Fn(ControllerButton) -> bool,
+        dt: f32,
+    ) -> io::Result<()> {
+        if !self.settings.enabled {
+            // let go of everything when being turned off
+            return self.release();
+        }
+
+        if self.device.is_none() {
+            self.device = Some(bind_keyboard_mouse()?);
+        }
+
+        let mut pressed = BTreeSet::new();
+        let mut events = Vec::new();
+
+        for button in ControllerButton::ALL {
+            if button != ControllerButton::None && input.pressed(button) && !claimed(button) {
+                pressed.extend(self.settings.button_keys(button).keys());
+            }
+        }
+
+        let [x, y] = axes;
+        match self.settings.stick {
+            KeyboardMouseStick::Mouse => {
+                let speed = |v: f32| v.signum() * v.abs().powf(self.settings.acceleration) * self.settings.sensitivity;
+                let y = if self.settings.invert_y { y } else { -y };
+
+                let motion = [self.remainder[0] + speed(x) * dt, self.remainder[1] + speed(y) * dt];
+                let counts = motion.map(f32::trunc);
+                self.remainder = [motion[0] - counts[0], motion[1] - counts[1]];
+
+                if counts[0] != 0.0 {
+                    events.push(InputEvent::new(2, RelativeAxisCode::REL_X.0, counts[0] as i32));
+                }
+                if counts[1] != 0.0 {
+                    events.push(InputEvent::new(2, RelativeAxisCode::REL_Y.0, counts[1] as i32));
+                }
+            }
+            KeyboardMouseStick::Keys => {
+                let period = self.settings.pulse_period.max(1.0);
+                let phase = (self.started.elapsed().as_secs_f32() * 1000.0 % period) / period;
+                // keys are held for the part of each cycle proportional to deflection
+                let [up, down, left, right] = &self.settings.stick_keys;
+                for (amount, keys) in [(y, up), (-y, down), (-x, left), (x, right)] {
+                    if amount > 0.0 && phase < amount {
+                        pressed.extend(keys.keys());
+                    }
+                }
+            }
+        }
+
+        for key in self.pressed.difference(&pressed) {
+            events.push(InputEvent::new(1, key.0, 0));
+        }
+        for key in pressed.difference(&self.pressed) {
+            events.push(InputEvent::new(1, key.0, 1));
+        }
+        self.pressed = pressed;
+
+        match &mut self.device {
+            Some(device) if !events.is_empty() => device.emit(&events),
+            _ => Ok(()),
+        }
+    }
+}
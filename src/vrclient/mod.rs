@@ -1,12 +1,20 @@
-use crate::pipe::{Hand, UI2VR, VR2UI, VRInputBounds, VRSystemFailure, VRSystemInformation};
-use crate::util;
+use crate::pipe::{Hand, UI2VR, VR2UI, VRSystemFailure, VRSystemInformation, VRTelemetry};
+use crate::telemetry::Telemetry;
+use crate::util::runtime::Runtime;
+use crate::vrclient::{
+    ff::FF_EFFECTS_MAX,
+    output::{Actions, Output, SessionStatus},
+};
 use ash::vk::{self, Handle};
 use evdev::uinput::VirtualDevice;
-use evdev::{AbsInfo, AbsoluteAxisCode, AttributeSet, AttributeSetRef, InputEvent, KeyCode, UinputAbsSetup};
+use evdev::{AbsInfo, AbsoluteAxisCode, AttributeSet, AttributeSetRef, FFEffectCode, KeyCode, UinputAbsSetup};
 use openxr as xr;
 use openxr::{Fovf, Posef};
 use std::{
+    ffi::CString,
     io,
+    os::fd::AsRawFd,
+    path::Path,
     sync::{
         Arc,
         atomic::{AtomicBool, Ordering},
@@ -26,10 +34,6 @@ struct Swapchain {
 
 /// Maximum number of frames in flight
 const PIPELINE_DEPTH: u32 = 2;
-const NE: f32 = 0.25;
-const NW: f32 = -0.25;
-const SE: f32 = 0.75;
-const SW: f32 = -0.75;
 
 pub struct VRClient {}
 
@@ -78,30 +82,117 @@ macro_rules! io_unwrap {
     };
 }
 
+mod engage;
+mod failsafe;
+mod ff;
+mod haptics;
+mod kbm;
+mod output;
+mod replay;
+mod trim;
+mod yaw;
+
 #[profiling::function]
-fn bind_gamepad(axes: &[UinputAbsSetup], keys: &AttributeSetRef<KeyCode>) -> io::Result<VirtualDevice> {
+fn bind_gamepad(
+    axes: &[UinputAbsSetup],
+    keys: &AttributeSetRef<KeyCode>,
+    ff: &AttributeSetRef<FFEffectCode>,
+) -> io::Result<VirtualDevice> {
     let mut device = VirtualDevice::builder()?.name("FlightWand Virtual Flight Stick");
     for axis in axes {
         device = device.with_absolute_axis(axis)?
     }
 
-    device.with_keys(keys)?.build()
+    let device = device.with_keys(keys)?.with_ff(ff)?.with_ff_effects_max(FF_EFFECTS_MAX).build()?;
+
+    // force feedback requests are polled from the main loop, which can't wait on them
+    unsafe {
+        let fd = device.as_raw_fd();
+        let flags = libc::fcntl(fd, libc::F_GETFL);
+        if flags < 0 || libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok(device)
+}
+
+/// Asks a runtime that only exports the loader negotiation interface for its `xrGetInstanceProcAddr`,
+/// the way the OpenXR loader would.
+///
+/// # Safety
+///
+/// `library` has to be an OpenXR runtime.
+unsafe fn negotiate(library: &Path) -> Option<xr::Entry> {
+    use xr::sys::loader::{
+        CURRENT_LOADER_RUNTIME_VERSION, FnNegotiateLoaderRuntimeInterface, XrNegotiateLoaderInfo,
+        XrNegotiateRuntimeRequest,
+    };
+
+    let name = CString::new(library.as_os_str().as_encoded_bytes()).ok()?;
+    unsafe {
+        // the runtime is used until FlightWand exits, so it's never unloaded
+        let handle = libc::dlopen(name.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL);
+        if handle.is_null() {
+            return None;
+        }
+        let symbol = libc::dlsym(handle, c"xrNegotiateLoaderRuntimeInterface".as_ptr());
+        if symbol.is_null() {
+            return None;
+        }
+        let negotiate: FnNegotiateLoaderRuntimeInterface = std::mem::transmute(symbol);
+
+        let loader_info = XrNegotiateLoaderInfo {
+            ty: XrNegotiateLoaderInfo::TYPE,
+            struct_version: XrNegotiateLoaderInfo::VERSION,
+            struct_size: size_of::<XrNegotiateLoaderInfo>(),
+            min_interface_version: 1,
+            max_interface_version: CURRENT_LOADER_RUNTIME_VERSION,
+            min_api_version: xr::Version::new(1, 0, 0),
+            max_api_version: xr::Version::new(1, 0x3ff, 0xfff),
+        };
+        let mut request = XrNegotiateRuntimeRequest {
+            ty: XrNegotiateRuntimeRequest::TYPE,
+            struct_version: XrNegotiateRuntimeRequest::VERSION,
+            struct_size: size_of::<XrNegotiateRuntimeRequest>(),
+            runtime_interface_version: 0,
+            runtime_api_version: xr::Version::new(0, 0, 0),
+            get_instance_proc_addr: None,
+        };
+        if negotiate(&loader_info, &mut request) != xr::sys::Result::SUCCESS {
+            return None;
+        }
+        xr::Entry::from_get_instance_proc_addr(request.get_instance_proc_addr?).ok()
+    }
+}
+
+/// Loads `runtime` directly, or through its loader negotiation interface for runtimes that only
+/// export that.
+fn load_runtime(runtime: &Runtime) -> Result<xr::Entry, xr::LoadError> {
+    log::info!("Loading OpenXR runtime from {}", runtime.manifest.display());
+    unsafe { xr::Entry::load_from(&runtime.library).or_else(|err| negotiate(&runtime.library).ok_or(err)) }
 }
 
 #[profiling::all_functions]
 impl VRClient {
-    pub fn run(tx: std::sync::mpsc::Sender<VR2UI>, rx: std::sync::mpsc::Receiver<UI2VR>) {
+    pub fn run(
+        tx: std::sync::mpsc::Sender<VR2UI>,
+        rx: std::sync::mpsc::Receiver<UI2VR>,
+        telemetry: Telemetry<VRTelemetry>,
+    ) {
         std::thread::Builder::new()
             .name("VRClient".to_owned())
             .spawn(|| {
-                VRClient::run_internal(tx, rx);
+                VRClient::run_internal(tx, rx, telemetry);
             })
             .expect("TODO: panic message");
     }
 
-    fn run_internal(tx: std::sync::mpsc::Sender<VR2UI>, rx: std::sync::mpsc::Receiver<UI2VR>) {
-        let mut identity: [f32; 3] = [-0.02, 0.2, -1.0];
-        let mut bounds = VRInputBounds::default();
+    fn run_internal(
+        tx: std::sync::mpsc::Sender<VR2UI>,
+        rx: std::sync::mpsc::Receiver<UI2VR>,
+        telemetry: Telemetry<VRTelemetry>,
+    ) {
         let abs_setup = AbsInfo::new(0, i16::MIN.into(), i16::MAX.into(), 0, 0, i16::MAX.into());
 
         let axis_x = UinputAbsSetup::new(AbsoluteAxisCode::ABS_X, abs_setup);
@@ -116,18 +207,56 @@ impl VRClient {
         keys.insert(KeyCode::BTN_SOUTH);
         keys.insert(KeyCode::BTN_WEST);
 
-        let mut device = io_unwrap!(tx, bind_gamepad(&[axis_x, axis_y, axis_z], &keys));
+        let mut ff = AttributeSet::<FFEffectCode>::new();
+        ff.insert(FFEffectCode::FF_RUMBLE);
+        ff.insert(FFEffectCode::FF_CONSTANT);
+        ff.insert(FFEffectCode::FF_PERIODIC);
+        ff.insert(FFEffectCode::FF_GAIN);
+
+        let mut device = io_unwrap!(tx, bind_gamepad(&[axis_x, axis_y, axis_z], &keys, &ff));
+        match device.get_syspath() {
+            Ok(path) => {
+                let _ = tx.send(VR2UI::GamepadDevice(path));
+            }
+            Err(err) => log::warn!("Couldn't find the virtual gamepad under /sys: {}", err),
+        }
 
         // Handle interrupts gracefully
         let running = Arc::new(AtomicBool::new(true));
 
-        let entry = unsafe {
-            match xr::Entry::load() {
-                Ok(entry) => entry,
-                Err(err) => {
-                    let _ = tx.send(VR2UI::Failure(VRSystemFailure::EntryCreation(err)));
+        let hand: &str;
+        let mut runtime = None;
+        'wait_for_startup: loop {
+            profiling::scope!("VRClient::wait_for_startup");
+            // nothing else to do until the frontend says so
+            let Ok(msg) = rx.recv() else { return };
+            match msg {
+                UI2VR::SelectRuntime(chosen) => {
+                    runtime = chosen;
+                }
+                UI2VR::Start(chosen) => {
+                    match chosen {
+                        Hand::Left => hand = "left",
+                        Hand::Right => hand = "right",
+                    }
+                    break 'wait_for_startup;
+                }
+                UI2VR::Shutdown => {
                     return;
                 }
+                _ => {}
+            }
+        }
+
+        let entry = match runtime {
+            Some(runtime) => load_runtime(&runtime),
+            None => unsafe { xr::Entry::load() },
+        };
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                let _ = tx.send(VR2UI::Failure(VRSystemFailure::EntryCreation(err)));
+                return;
             }
         };
 
@@ -143,6 +272,8 @@ impl VRClient {
         let mut enabled_extensions = xr::ExtensionSet::default();
         enabled_extensions.khr_vulkan_enable2 = true;
         enabled_extensions.mnd_headless = true;
+        // lets the output thread sample poses at its own timestamps
+        enabled_extensions.khr_convert_timespec_time = available_extensions.khr_convert_timespec_time;
         //enabled_extensions.extx_overlay = true;
 
         let xr_application_info = xr::ApplicationInfo {
@@ -267,32 +398,16 @@ impl VRClient {
                 },)
             );
 
-            let hand: &str;
-            'wait_for_startup: loop {
-                profiling::scope!("VRClient::wait_for_startup");
-                while let Ok(msg) = rx.try_recv() {
-                    match msg {
-                        UI2VR::Start(chosen) => {
-                            match chosen {
-                                Hand::Left => hand = "left",
-                                Hand::Right => hand = "right",
-                            }
-                            break 'wait_for_startup;
-                        }
-                        UI2VR::Shutdown => {
-                            return;
-                        }
-                        _ => {}
-                    }
-                }
-            }
-
             let action_set = xr_unwrap!(tx, xr_instance.create_action_set("input", "input pose information", 0));
 
-            let right_action = xr_unwrap!(tx, action_set.create_action::<xr::Posef>("hand", "Controller", &[]));
+            // both poses are bound so the source can be switched without recreating the action set
+            let aim_pose = xr_unwrap!(tx, action_set.create_action::<xr::Posef>("hand", "Controller", &[]));
+            let grip_pose = xr_unwrap!(tx, action_set.create_action::<xr::Posef>("grip_pose", "Controller Grip", &[]));
 
             let trackpad_x = xr_unwrap!(tx, action_set.create_action::<f32>("trackpad_x", "Trackpad X", &[]));
             let trackpad_y = xr_unwrap!(tx, action_set.create_action::<f32>("trackpad_y", "Trackpad Y", &[]));
+            let trackpad_touch =
+                xr_unwrap!(tx, action_set.create_action::<bool>("trackpad_touch", "Trackpad Touch", &[]));
             let trackpad_click =
                 xr_unwrap!(tx, action_set.create_action::<bool>("trackpad_click", "Trackpad Click", &[]));
             let grip = xr_unwrap!(tx, action_set.create_action::<bool>("grip", "Grip", &[]));
@@ -300,12 +415,18 @@ impl VRClient {
 
             let trigger = xr_unwrap!(tx, action_set.create_action::<f32>("trigger", "Trigger", &[]));
 
+            let haptic = xr_unwrap!(tx, action_set.create_action::<xr::Haptic>("haptic", "Haptic", &[]));
+
             // BINDINGS
             let bindings = &[
                 xr::Binding::new(
-                    &right_action,
+                    &aim_pose,
                     xr_unwrap!(tx, xr_instance.string_to_path(&format!("/user/hand/{hand}/input/aim/pose"))),
                 ),
+                xr::Binding::new(
+                    &grip_pose,
+                    xr_unwrap!(tx, xr_instance.string_to_path(&format!("/user/hand/{hand}/input/grip/pose"))),
+                ),
                 xr::Binding::new(
                     &trackpad_x,
                     xr_unwrap!(tx, xr_instance.string_to_path(&format!("/user/hand/{hand}/input/trackpad/x"))),
@@ -314,6 +435,10 @@ impl VRClient {
                     &trackpad_y,
                     xr_unwrap!(tx, xr_instance.string_to_path(&format!("/user/hand/{hand}/input/trackpad/y"))),
                 ),
+                xr::Binding::new(
+                    &trackpad_touch,
+                    xr_unwrap!(tx, xr_instance.string_to_path(&format!("/user/hand/{hand}/input/trackpad/touch"))),
+                ),
                 xr::Binding::new(
                     &trackpad_click,
                     xr_unwrap!(tx, xr_instance.string_to_path(&format!("/user/hand/{hand}/input/trackpad/click"))),
@@ -330,6 +455,10 @@ impl VRClient {
                     &trigger,
                     xr_unwrap!(tx, xr_instance.string_to_path(&format!("/user/hand/{hand}/input/trigger/value"))),
                 ),
+                xr::Binding::new(
+                    &haptic,
+                    xr_unwrap!(tx, xr_instance.string_to_path(&format!("/user/hand/{hand}/output/haptic"))),
+                ),
             ];
             xr_unwrap!(
                 tx,
@@ -341,8 +470,9 @@ impl VRClient {
 
             xr_unwrap!(tx, session.attach_action_sets(&[&action_set]));
 
-            let right_space =
-                xr_unwrap!(tx, right_action.create_space(session.clone(), xr::Path::NULL, xr::Posef::IDENTITY));
+            let aim_space = xr_unwrap!(tx, aim_pose.create_space(session.clone(), xr::Path::NULL, xr::Posef::IDENTITY));
+            let grip_space =
+                xr_unwrap!(tx, grip_pose.create_space(session.clone(), xr::Path::NULL, xr::Posef::IDENTITY));
 
             let stage =
                 xr_unwrap!(tx, session.create_reference_space(xr::ReferenceSpaceType::STAGE, xr::Posef::IDENTITY));
@@ -373,6 +503,32 @@ impl VRClient {
             // tell the frontend we're good!
             let _ = tx.send(VR2UI::Running(VRSystemInformation { system_properties }));
 
+            let status = SessionStatus::default();
+            let (output_tx, output_rx) = std::sync::mpsc::channel::<UI2VR>();
+            let output = Output {
+                instance: xr_instance.clone(),
+                session: session.clone(),
+                action_set,
+                actions: Actions {
+                    aim_pose,
+                    grip_pose,
+                    trackpad_x,
+                    trackpad_y,
+                    trackpad_touch,
+                    trackpad_click,
+                    grip,
+                    menu,
+                    trigger,
+                    haptic,
+                },
+                aim_space,
+                grip_space,
+                device,
+                status: status.clone(),
+                telemetry,
+            }
+            .spawn(tx.clone(), output_rx);
+
             // Main loop
             let mut swapchain = None;
             let swapchain_create_info = xr::SwapchainCreateInfo {
@@ -422,8 +578,10 @@ impl VRClient {
                                 xr::SessionState::READY => {
                                     xr_unwrap!(tx, session.begin(VIEW_TYPE));
                                     session_running = true;
+                                    status.running.store(true, Ordering::Relaxed);
                                 }
                                 xr::SessionState::STOPPING => {
+                                    status.running.store(false, Ordering::Relaxed);
                                     xr_unwrap!(tx, session.end());
                                     session_running = false;
                                 }
@@ -446,16 +604,18 @@ impl VRClient {
                 while let Ok(ev) = rx.try_recv() {
                     match ev {
                         UI2VR::Shutdown => break 'main_loop,
-                        UI2VR::UpdateIdentity(new_id) => {
-                            identity = new_id;
-                        }
-                        UI2VR::UpdateBounds(new_bounds) => {
-                            bounds = new_bounds;
+                        // everything else is about the stick output
+                        ev => {
+                            let _ = output_tx.send(ev);
                         }
-                        _ => {}
                     }
                 }
 
+                // the output thread reports its own failures
+                if output.is_finished() {
+                    break 'main_loop;
+                }
+
                 if !session_running {
                     profiling::scope!("!session_running");
                     // Don't grind up the CPU
@@ -465,6 +625,7 @@ impl VRClient {
 
                 let xr_frame_state = xr_unwrap!(tx, frame_wait.wait());
                 xr_unwrap!(tx, frame_stream.begin());
+                status.display_time.store(xr_frame_state.predicted_display_time.as_nanos(), Ordering::Relaxed);
 
                 if !xr_frame_state.should_render {
                     xr_unwrap!(tx, frame_stream.end(xr_frame_state.predicted_display_time, env_blend_mode, &[],));
@@ -487,69 +648,6 @@ impl VRClient {
 
                 vk_unwrap!(tx, vk_device.end_command_buffer(cmd));
 
-                xr_unwrap!(tx, session.sync_actions(&[(&action_set).into()]));
-
-                let pose = xr_unwrap!(tx, right_space.locate(&stage, xr_frame_state.predicted_display_time));
-
-                if xr_unwrap!(tx, right_action.is_active(&session, xr::Path::NULL)) {
-                    // don't unwrap because sometimes the UI can shut down in the middle of this function
-                    let _ = tx.send(VR2UI::RotationUpdate(pose.pose.orientation));
-                }
-
-                let trigger = xr_unwrap!(tx, trigger.state(&session, xr::Path::NULL));
-                let trackpad_x = xr_unwrap!(tx, trackpad_x.state(&session, xr::Path::NULL));
-                let trackpad_y = xr_unwrap!(tx, trackpad_y.state(&session, xr::Path::NULL));
-                let trackpad_click = xr_unwrap!(tx, trackpad_click.state(&session, xr::Path::NULL));
-                let grip = xr_unwrap!(tx, grip.state(&session, xr::Path::NULL));
-                let menu = xr_unwrap!(tx, menu.state(&session, xr::Path::NULL));
-
-                if trackpad_x.is_active && trackpad_y.is_active && trackpad_click.is_active {
-                    profiling::scope!("Input processing");
-                    let ang = f32::atan2(trackpad_x.current_state, trackpad_y.current_state) / std::f32::consts::PI;
-                    let distance = f32::sqrt(
-                        trackpad_x.current_state * trackpad_x.current_state
-                            + trackpad_y.current_state * trackpad_y.current_state,
-                    );
-                    let act = distance > 0.35 && trackpad_click.current_state;
-
-                    let ev_north =
-                        InputEvent::new(1, KeyCode::BTN_NORTH.0, if (ang < NE && ang > NW) && act { 1 } else { 0 });
-                    let ev_east =
-                        InputEvent::new(1, KeyCode::BTN_EAST.0, if (ang < SE && ang > NE) && act { 1 } else { 0 });
-                    let ev_west =
-                        InputEvent::new(1, KeyCode::BTN_WEST.0, if (ang < NW && ang > SW) && act { 1 } else { 0 });
-                    let ev_south =
-                        InputEvent::new(1, KeyCode::BTN_SOUTH.0, if (ang < SW || ang > SE) && act { 1 } else { 0 });
-
-                    let ev_start = InputEvent::new(1, KeyCode::BTN_START.0, if menu.current_state { 1 } else { 0 });
-                    let ev_grip = InputEvent::new(1, KeyCode::BTN_TR2.0, if grip.current_state { 1 } else { 0 });
-
-                    io_unwrap!(tx, device.emit(&[ev_north, ev_east, ev_west, ev_south, ev_start, ev_grip]));
-                }
-
-                let rot = util::modifier(
-                    &[
-                        pose.pose.orientation.x,
-                        pose.pose.orientation.y,
-                        pose.pose.orientation.z,
-                        pose.pose.orientation.w,
-                    ],
-                    identity,
-                );
-
-                let [x, y] = util::rot_to_joy(&[rot[0], rot[2]], bounds);
-                let length = f32::sqrt(x * x + y * y);
-
-                let x = if length < bounds.deadzone { 0.0 } else { x / bounds.stick_max * i16::MAX as f32 };
-                let y = if length < bounds.deadzone { 0.0 } else { y / bounds.stick_max * i16::MAX as f32 };
-
-                let ev_x = InputEvent::new(3, AbsoluteAxisCode::ABS_X.0, x as i32);
-                let ev_y = InputEvent::new(3, AbsoluteAxisCode::ABS_Y.0, y as i32);
-                let _ev_t =
-                    InputEvent::new(3, AbsoluteAxisCode::ABS_GAS.0, (trigger.current_state * i16::MAX as f32) as i32);
-
-                io_unwrap!(tx, device.emit(&[ev_x, ev_y]));
-
                 // Wait until the image is available to render to before beginning work on the GPU. The
                 // compositor could still be reading from it.
                 xr_unwrap!(tx, swapchain.handle.wait_image(xr::Duration::INFINITE));
@@ -586,9 +684,11 @@ impl VRClient {
                 frame = (frame + 1) % PIPELINE_DEPTH as usize;
             } // 'main_loop
 
+            drop(output);
+
             // OpenXR MUST be allowed to clean up before we destroy Vulkan resources it could touch, so
             // first we must drop all its handles.
-            drop((session, frame_wait, frame_stream, stage, action_set, right_space, right_action));
+            drop((session, frame_wait, frame_stream, stage));
         }
 
         println!("VRClient shut down");
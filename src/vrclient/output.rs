@@ -0,0 +1,661 @@
+use crate::mapping::{self, ControllerState, Pose, StickFilter, StickPipeline};
+use crate::pipe::{
+    ActionReading, ControllerButton, DEFAULT_OUTPUT_RATE, EngageSettings, FailsafeSettings, ForceFeedbackSettings,
+    GAMEPAD_BUTTONS, GimbalSettings, HapticEvent, HapticPulse, HapticSettings, InputReadings, KeyboardMouseSettings,
+    OutputState, PoseSource, RecordingSettings, ReferenceSpace, ReplaySettings, TrackingSettings, TrimSettings, UI2VR,
+    VR2UI, VRInputBounds, VRSystemFailure, VRTelemetry, YawReference,
+};
+use crate::recording::{self, Frame, Recorder};
+use crate::telemetry::Telemetry;
+use crate::util;
+use crate::vrclient::{
+    engage::Clutch,
+    failsafe::Failsafe,
+    ff::ForceFeedback,
+    haptics::{self, HapticTriggers},
+    kbm::KeyboardMouse,
+    replay::Replay,
+    trim::Trim,
+    yaw::YawFrame,
+};
+use evdev::{AbsoluteAxisCode, InputEvent, uinput::VirtualDevice};
+use openxr as xr;
+use std::{
+    io,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicI64, Ordering},
+        mpsc,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+/// Force feedback is played as a series of pulses this long, renewed every tick while an effect is playing
+const FF_PULSE_MS: f32 = 50.0;
+/// How often rotation updates are sent to the frontend, regardless of the output rate
+const TELEMETRY_INTERVAL: Duration = Duration::from_millis(11);
+
+/// Every action the output thread reads from or writes to.
+pub struct Actions {
+    pub aim_pose: xr::Action<xr::Posef>,
+    pub grip_pose: xr::Action<xr::Posef>,
+    pub trackpad_x: xr::Action<f32>,
+    pub trackpad_y: xr::Action<f32>,
+    /// Only shown in the input monitor
+    pub trackpad_touch: xr::Action<bool>,
+    pub trackpad_click: xr::Action<bool>,
+    pub grip: xr::Action<bool>,
+    pub menu: xr::Action<bool>,
+    pub trigger: xr::Action<f32>,
+    pub haptic: xr::Action<xr::Haptic>,
+}
+
+/// One reading of the controller, live or replayed.
+struct Sample {
+    /// As located in the reference space
+    orientation: [f32; 4],
+    position: [f32; 3],
+    position_valid: bool,
+    tracked: bool,
+    /// Heading of the frame the controller is measured in
+    body_yaw: f32,
+    input: ControllerState,
+    /// Whether the digital inputs can be read
+    buttons_active: bool,
+    readings: InputReadings,
+    /// Whether the session is running, haptics can only be played while it is
+    session_running: bool,
+}
+
+impl Sample {
+    /// Stands in for the controller while the session isn't running, which is treated like lost tracking.
+    fn stopped() -> Self {
+        Sample {
+            orientation: [0.0, 0.0, 0.0, 1.0],
+            position: [0.0; 3],
+            position_valid: false,
+            tracked: false,
+            body_yaw: 0.0,
+            input: ControllerState::default(),
+            buttons_active: false,
+            readings: InputReadings::default(),
+            session_running: false,
+        }
+    }
+}
+
+/// What the input monitor shows for a replayed frame. Only what's recorded is active.
+fn replayed_readings(input: &ControllerState, previous: &ControllerState) -> InputReadings {
+    fn reading<T: PartialEq + Copy>(value: T, previous: T) -> ActionReading<T> {
+        ActionReading { value, active: true, changed: value != previous }
+    }
+    InputReadings {
+        pose_active: true,
+        pose_tracked: true,
+        trigger: reading(input.trigger, previous.trigger),
+        trackpad_x: reading(input.trackpad[0], previous.trackpad[0]),
+        trackpad_y: reading(input.trackpad[1], previous.trackpad[1]),
+        trackpad_touch: ActionReading::default(),
+        trackpad_click: reading(input.trackpad_click, previous.trackpad_click),
+        grip: reading(input.grip, previous.grip),
+        menu: reading(input.menu, previous.menu),
+        replayed: true,
+    }
+}
+
+/// Session state the frame loop shares with the output thread.
+#[derive(Clone, Default)]
+pub struct SessionStatus {
+    /// Whether the session has begun, and actions can be synced
+    pub running: Arc<AtomicBool>,
+    /// Latest predicted display time in nanoseconds, for runtimes that can't convert clock times
+    pub display_time: Arc<AtomicI64>,
+    /// Tells the output thread to stop
+    pub stop: Arc<AtomicBool>,
+}
+
+/// Samples the controller and emits gamepad events at a fixed rate, independent of the XR frame loop.
+pub struct Output {
+    pub instance: xr::Instance,
+    pub session: xr::Session<xr::Vulkan>,
+    pub action_set: xr::ActionSet,
+    pub actions: Actions,
+    pub aim_space: xr::Space,
+    pub grip_space: xr::Space,
+    pub device: VirtualDevice,
+    pub status: SessionStatus,
+    pub telemetry: Telemetry<VRTelemetry>,
+}
+
+/// Stops and joins the output thread when dropped, so it lets go of its OpenXR handles before the
+/// frame loop tears the session down (even when bailing out early).
+pub struct OutputThread {
+    handle: Option<JoinHandle<()>>,
+    stop: Arc<AtomicBool>,
+}
+
+impl OutputThread {
+    pub fn is_finished(&self) -> bool { self.handle.as_ref().is_none_or(|handle| handle.is_finished()) }
+}
+
+impl Drop for OutputThread {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[profiling::all_functions]
+impl Output {
+    pub fn spawn(self, tx: mpsc::Sender<VR2UI>, rx: mpsc::Receiver<UI2VR>) -> OutputThread {
+        let stop = self.status.stop.clone();
+        let handle = std::thread::Builder::new()
+            .name("VRClient output".to_owned())
+            .spawn(move || self.run(tx, rx))
+            .expect("Failed to spawn the output thread");
+
+        OutputThread { handle: Some(handle), stop }
+    }
+
+    /// The current time in the runtime's clock.
+    fn now(&self) -> xr::Time {
+        if let Some(ext) = self.instance.exts().khr_convert_timespec_time {
+            let mut timespec = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+            let mut time = xr::Time::from_nanos(0);
+            let result = unsafe {
+                libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut timespec);
+                (ext.convert_timespec_time_to_time)(self.instance.as_raw(), &timespec, &mut time)
+            };
+            if result == xr::sys::Result::SUCCESS {
+                return time;
+            }
+        }
+
+        xr::Time::from_nanos(self.status.display_time.load(Ordering::Relaxed))
+    }
+
+    /// The controller pose action and space for `source`.
+    fn pose(&self, source: PoseSource) -> (&xr::Action<xr::Posef>, &xr::Space) {
+        match source {
+            PoseSource::Aim => (&self.actions.aim_pose, &self.aim_space),
+            PoseSource::Grip => (&self.actions.grip_pose, &self.grip_space),
+        }
+    }
+
+    fn create_reference_space(&self, space: ReferenceSpace) -> xr::Result<xr::Space> {
+        let ty = match space {
+            ReferenceSpace::Stage => xr::ReferenceSpaceType::STAGE,
+            ReferenceSpace::Local => xr::ReferenceSpaceType::LOCAL,
+            ReferenceSpace::View => xr::ReferenceSpaceType::VIEW,
+        };
+        self.session.create_reference_space(ty, xr::Posef::IDENTITY)
+    }
+
+    /// Syncs the actions, returning false if the session isn't running.
+    fn sync(&self) -> xr::Result<bool> {
+        if !self.status.running.load(Ordering::Relaxed) {
+            return Ok(false);
+        }
+        match self.session.sync_actions(&[(&self.action_set).into()]) {
+            Ok(()) => Ok(true),
+            // the session can stop between checking and syncing
+            Err(xr::sys::Result::ERROR_SESSION_NOT_RUNNING) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn run(mut self, tx: mpsc::Sender<VR2UI>, rx: mpsc::Receiver<UI2VR>) {
+        let mut identity: [f32; 3] = [-0.02, 0.2, -1.0];
+        let mut bounds = VRInputBounds::default();
+        let mut trim = Trim::new(TrimSettings::default());
+        let mut clutch = Clutch::new(EngageSettings::default());
+        let mut failsafe = Failsafe::new(FailsafeSettings::default());
+        let mut haptic_triggers = HapticTriggers::new(HapticSettings::default());
+        let mut force_feedback = ForceFeedback::new(ForceFeedbackSettings::default());
+        let mut keyboard_mouse = KeyboardMouse::new(KeyboardMouseSettings::default());
+        // haptic pulses to play on the next tick the session is running
+        let mut pending_haptics = Vec::new();
+        let mut previous_input = ControllerState::default();
+        // the input as the functions saw it, which is nothing while tracking is lost
+        let mut previous_buttons = ControllerState::default();
+        // last known controller orientation, for recentering
+        let mut orientation = [0.0, 0.0, 0.0, 1.0];
+        let mut rate = DEFAULT_OUTPUT_RATE;
+        let mut tracking = TrackingSettings::default();
+        let mut reference = xr_unwrap!(tx, self.create_reference_space(tracking.space));
+        let view = xr_unwrap!(tx, self.create_reference_space(ReferenceSpace::View));
+        let mut yaw_frame = YawFrame::new();
+        let mut gimbal = GimbalSettings::default();
+        // last known controller position, for capturing the pivot
+        let mut position = [0.0; 3];
+        let mut recording_settings = RecordingSettings::default();
+        let mut recorder: Option<Recorder> = None;
+        // whether recording should be running, if that needs to change
+        let mut record_request = None;
+        let mut replay: Option<Replay> = None;
+        let mut replay_settings = ReplaySettings::default();
+        // raw input since the last telemetry
+        let mut readings = InputReadings::default();
+        // what the game was last sent, which sticks around until something new is
+        let mut gamepad = OutputState::default();
+        let mut stick_filter = StickFilter::default();
+        // neutral is captured again on the next sample after the tracking changes
+        let mut recapture = false;
+        // whether the session has run yet, before that nothing has reached the game
+        let mut session_started = false;
+
+        let mut next_tick = Instant::now();
+        let mut last_telemetry = Instant::now();
+
+        while !self.status.stop.load(Ordering::Relaxed) {
+            profiling::scope!("VRClient::output_loop");
+            while let Ok(ev) = rx.try_recv() {
+                match ev {
+                    UI2VR::UpdateIdentity(new_id) => {
+                        identity = new_id;
+                    }
+                    UI2VR::UpdateBounds(new_bounds) => {
+                        bounds = new_bounds;
+                    }
+                    UI2VR::UpdateTrim(settings) => {
+                        trim.settings = settings;
+                    }
+                    UI2VR::ResetTrim => {
+                        trim.reset();
+                        let _ = tx.send(VR2UI::TrimUpdate(trim.offset()));
+                    }
+                    UI2VR::UpdateEngage(settings) => {
+                        clutch.settings = settings;
+                    }
+                    UI2VR::Recenter => {
+                        identity = util::capture_up(&orientation);
+                        let _ = tx.send(VR2UI::IdentityUpdate(identity));
+                        pending_haptics.extend(haptic_triggers.pulse(HapticEvent::Recenter));
+                    }
+                    UI2VR::UpdateHaptics(settings) => {
+                        haptic_triggers.settings = settings;
+                    }
+                    UI2VR::TestHaptic(pulse) => {
+                        pending_haptics.push(pulse);
+                    }
+                    UI2VR::UpdateForceFeedback(settings) => {
+                        force_feedback.settings = settings;
+                    }
+                    UI2VR::UpdateOutputRate(new_rate) => {
+                        rate = new_rate.max(1);
+                    }
+                    UI2VR::UpdateKeyboardMouse(settings) => {
+                        keyboard_mouse.settings = settings;
+                    }
+                    UI2VR::UpdateTracking(settings) => {
+                        if settings.space != tracking.space {
+                            reference = xr_unwrap!(tx, self.create_reference_space(settings.space));
+                        }
+                        recapture |= settings.frame_differs(&tracking);
+                        tracking = settings;
+                    }
+                    UI2VR::UpdateRecording(settings) => {
+                        recording_settings = settings;
+                    }
+                    UI2VR::StartRecording => {
+                        record_request = Some(true);
+                    }
+                    UI2VR::StopRecording => {
+                        record_request = Some(false);
+                    }
+                    UI2VR::StartReplay(frames) => {
+                        let started = Replay::new(frames, replay_settings);
+                        let _ = tx.send(VR2UI::ReplayUpdate(Some(started.progress())));
+                        replay = Some(started);
+                    }
+                    UI2VR::StopReplay if replay.take().is_some() => {
+                        let _ = tx.send(VR2UI::ReplayUpdate(None));
+                    }
+                    UI2VR::UpdateReplay(settings) => {
+                        replay_settings = settings;
+                        if let Some(replay) = &mut replay {
+                            replay.settings = settings;
+                        }
+                    }
+                    UI2VR::SeekReplay(position) => {
+                        if let Some(replay) = &mut replay {
+                            replay.seek(Duration::from_secs_f32(position.max(0.0)));
+                        }
+                    }
+                    UI2VR::UpdateFailsafe(settings) => {
+                        failsafe.settings = settings;
+                    }
+                    UI2VR::UpdateGimbal(settings) => {
+                        gimbal = settings;
+                    }
+                    UI2VR::CapturePivot => {
+                        gimbal.pivot = [position[0], position[1] - gimbal.length, position[2]];
+                        let _ = tx.send(VR2UI::PivotUpdate(gimbal.pivot));
+                        pending_haptics.extend(haptic_triggers.pulse(HapticEvent::Recenter));
+                    }
+                    UI2VR::CaptureSeat => {
+                        yaw_frame.capture_seat();
+                        recapture |= tracking.yaw == YawReference::Seat;
+                    }
+                    _ => {}
+                }
+            }
+
+            match record_request.take() {
+                Some(true) if recorder.is_none() => {
+                    let calibration = serde_json::json!({
+                        "up": identity,
+                        "deadzone": bounds.deadzone,
+                        "stick_max": bounds.stick_max,
+                        "smoothing": bounds.smoothing,
+                        "ranges": bounds.ranges.map(|ranges| [ranges.right, ranges.left, ranges.forward, ranges.back]),
+                        "trim": trim.offset(),
+                        "pose": tracking.pose.to_string(),
+                        "space": tracking.space.to_string(),
+                        "yaw": tracking.yaw.to_string(),
+                        "gimbal": gimbal.enabled.then_some(serde_json::json!({
+                            "pivot": gimbal.pivot,
+                            "length": gimbal.length,
+                            "orientation_blend": gimbal.orientation_blend,
+                        })),
+                        "rate": rate,
+                    });
+                    let created = recording::default_dir()
+                        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no home directory"))
+                        .and_then(|dir| Recorder::create(&dir, calibration));
+                    match created {
+                        Ok(created) => {
+                            let _ = tx.send(VR2UI::RecordingUpdate(Some(created.path.clone())));
+                            recorder = Some(created);
+                        }
+                        Err(err) => log::error!("Couldn't start recording: {}", err),
+                    }
+                }
+                Some(false) => {
+                    if let Some(stopped) = recorder.take() {
+                        if let Err(err) = stopped.finish() {
+                            log::error!("Couldn't finish recording: {}", err);
+                        }
+                        let _ = tx.send(VR2UI::RecordingUpdate(None));
+                    }
+                }
+                _ => {}
+            }
+
+            // games wait on their uploads, so keep answering even while the session is idle
+            io_unwrap!(tx, force_feedback.poll(&mut self.device));
+
+            'sample: {
+                let sample = if let Some(replay) = &mut replay {
+                    let frame = replay.advance();
+                    let input = ControllerState {
+                        trigger: frame.trigger,
+                        trackpad: frame.trackpad,
+                        trackpad_click: frame.trackpad_click,
+                        grip: frame.grip,
+                        menu: frame.menu,
+                    };
+                    Sample {
+                        orientation: frame.orientation,
+                        position: frame.position,
+                        position_valid: frame.tracked,
+                        tracked: frame.tracked,
+                        body_yaw: frame.body_yaw,
+                        input,
+                        buttons_active: true,
+                        readings: InputReadings {
+                            pose_tracked: frame.tracked,
+                            ..replayed_readings(&input, &previous_input)
+                        },
+                        // haptics aren't played during replays anyway
+                        session_running: true,
+                    }
+                } else if !xr_unwrap!(tx, self.sync()) {
+                    if !session_started {
+                        break 'sample;
+                    }
+                    // goes through the failsafe, so the stick doesn't stay where it was when the session stopped
+                    Sample::stopped()
+                } else {
+                    session_started = true;
+                    let (pose_action, pose_space) = self.pose(tracking.pose);
+                    let time = self.now();
+                    let pose = xr_unwrap!(tx, pose_space.locate(&reference, time));
+                    let head = xr_unwrap!(tx, view.locate(&reference, time));
+                    let head_yaw = head.location_flags.contains(xr::SpaceLocationFlags::ORIENTATION_VALID).then(|| {
+                        let o = head.pose.orientation;
+                        util::yaw(&[o.x, o.y, o.z, o.w])
+                    });
+                    let body_yaw = yaw_frame.update(tracking.yaw, tracking.yaw_smoothing, head_yaw, 1.0 / rate as f32);
+
+                    let pose_active = xr_unwrap!(tx, pose_action.is_active(&self.session, xr::Path::NULL));
+                    let tracked = pose_active
+                        && pose.location_flags.contains(
+                            xr::SpaceLocationFlags::ORIENTATION_VALID | xr::SpaceLocationFlags::ORIENTATION_TRACKED,
+                        );
+
+                    let trigger = xr_unwrap!(tx, self.actions.trigger.state(&self.session, xr::Path::NULL));
+                    let trackpad_x = xr_unwrap!(tx, self.actions.trackpad_x.state(&self.session, xr::Path::NULL));
+                    let trackpad_y = xr_unwrap!(tx, self.actions.trackpad_y.state(&self.session, xr::Path::NULL));
+                    let trackpad_touch =
+                        xr_unwrap!(tx, self.actions.trackpad_touch.state(&self.session, xr::Path::NULL));
+                    let trackpad_click =
+                        xr_unwrap!(tx, self.actions.trackpad_click.state(&self.session, xr::Path::NULL));
+                    let grip = xr_unwrap!(tx, self.actions.grip.state(&self.session, xr::Path::NULL));
+                    let menu = xr_unwrap!(tx, self.actions.menu.state(&self.session, xr::Path::NULL));
+
+                    let o = pose.pose.orientation;
+                    let p = pose.pose.position;
+                    Sample {
+                        orientation: [o.x, o.y, o.z, o.w],
+                        position: [p.x, p.y, p.z],
+                        position_valid: pose.location_flags.contains(xr::SpaceLocationFlags::POSITION_VALID),
+                        tracked,
+                        body_yaw,
+                        input: ControllerState {
+                            trigger: trigger.current_state,
+                            trackpad: [trackpad_x.current_state, trackpad_y.current_state],
+                            trackpad_click: trackpad_click.current_state,
+                            grip: grip.current_state,
+                            menu: menu.current_state,
+                        },
+                        buttons_active: trackpad_x.is_active && trackpad_y.is_active && trackpad_click.is_active,
+                        readings: InputReadings {
+                            pose_active,
+                            pose_tracked: tracked,
+                            trigger: trigger.into(),
+                            trackpad_x: trackpad_x.into(),
+                            trackpad_y: trackpad_y.into(),
+                            trackpad_touch: trackpad_touch.into(),
+                            trackpad_click: trackpad_click.into(),
+                            grip: grip.into(),
+                            menu: menu.into(),
+                            replayed: false,
+                        },
+                        session_running: true,
+                    }
+                };
+                let (tracked, input) = (sample.tracked, sample.input);
+                // replays only reach the game when asked to
+                let live = replay.as_ref().is_none_or(|replay| replay.settings.drive_gamepad);
+
+                if let Some((tracked, losses)) = failsafe.update(tracked) {
+                    let _ = tx.send(VR2UI::TrackingUpdate(tracked, losses));
+                }
+                // the pose is garbage while untracked, keep the last good one
+                if tracked {
+                    orientation = util::remove_yaw(&sample.orientation, sample.body_yaw);
+                    position = util::rotate_yaw(sample.position, -sample.body_yaw);
+                }
+
+                readings.accumulate(sample.readings);
+                // the output is sent on the same tick further down
+                let send_telemetry = last_telemetry.elapsed() >= TELEMETRY_INTERVAL;
+                if send_telemetry {
+                    self.telemetry.push(VRTelemetry::Input(readings));
+                    // changes from here on are news
+                    readings = InputReadings::default();
+                    // don't unwrap because sometimes the UI can shut down in the middle of this function
+                    if tracked {
+                        let [x, y, z, w] = orientation;
+                        let position = sample.position_valid.then_some(position);
+                        self.telemetry.push(VRTelemetry::Rotation(xr::Quaternionf { x, y, z, w }, position));
+                    }
+                    if let Some(replay) = &replay {
+                        self.telemetry.push(VRTelemetry::ReplayProgress(replay.progress()));
+                    }
+                }
+
+                // the old neutral means nothing in a different pose or space
+                if recapture && tracked {
+                    identity = util::capture_up(&orientation);
+                    let _ = tx.send(VR2UI::IdentityUpdate(identity));
+                    recapture = false;
+                }
+
+                // nothing is held down while tracking is lost
+                let buttons = if tracked { input } else { ControllerState::default() };
+
+                // losing tracking is up to the failsafe, it isn't letting go of the engage button
+                if tracked && let Some(engaged) = clutch.update(&buttons) {
+                    if engaged && clutch.settings.recapture_neutral {
+                        identity = util::capture_up(&orientation);
+                        let _ = tx.send(VR2UI::IdentityUpdate(identity));
+                        pending_haptics.extend(haptic_triggers.pulse(HapticEvent::Recenter));
+                    }
+                    let _ = tx.send(VR2UI::EngageUpdate(engaged));
+                    pending_haptics.extend(haptic_triggers.pulse(HapticEvent::ModeSwitch));
+                }
+
+                // inputs assigned to FlightWand functions don't reach the game
+                let (trim_settings, engage_button, record_button) =
+                    (trim.settings, clutch.settings.button, recording_settings.button);
+                let claimed = move |button: ControllerButton| {
+                    trim_settings.claims(button)
+                        || (button != ControllerButton::None && (engage_button == button || record_button == button))
+                };
+                // a replayed press shouldn't start recording the replay
+                if record_button != ControllerButton::None
+                    && replay.is_none()
+                    && input.just_pressed(&previous_input, record_button)
+                {
+                    record_request = Some(recorder.is_none());
+                    pending_haptics.extend(haptic_triggers.pulse(HapticEvent::ModeSwitch));
+                }
+                let mut emitted = Vec::new();
+                let joystick = !(keyboard_mouse.settings.enabled && keyboard_mouse.settings.replace_joystick);
+
+                let pipeline = StickPipeline { up: identity, bounds, gimbal };
+                let pose = Pose { orientation, position: sample.position_valid.then_some(position) };
+                let deflection = stick_filter.apply(pipeline.deflection(&pose), bounds.smoothing, 1.0 / rate as f32);
+                let mapped = pipeline.map_deflection(deflection, &buttons, claimed);
+
+                // nothing stays held on the gamepad once the controller or the gamepad output goes away
+                let released = !(sample.buttons_active && tracked && joystick && live);
+                let pressed = if released {
+                    gamepad.buttons.contains(&true).then_some([false; GAMEPAD_BUTTONS.len()])
+                } else {
+                    (!clutch.buttons_frozen()).then_some(mapped.buttons)
+                };
+                if let Some(pressed) = pressed {
+                    profiling::scope!("Input processing");
+                    let events: [InputEvent; GAMEPAD_BUTTONS.len()] =
+                        std::array::from_fn(|i| InputEvent::new(1, GAMEPAD_BUTTONS[i].1.0, pressed[i] as i32));
+                    io_unwrap!(tx, self.device.emit(&events));
+                    emitted.extend(events);
+                    gamepad.buttons = pressed;
+                }
+
+                if trim.update(&buttons, &previous_buttons, mapped.stick) {
+                    let _ = tx.send(VR2UI::TrimUpdate(trim.offset()));
+                    pending_haptics.extend(haptic_triggers.pulse(HapticEvent::TrimStep));
+                }
+                for event in haptic_triggers.update(&mapped, &bounds, buttons.trigger) {
+                    pending_haptics.extend(haptic_triggers.pulse(event));
+                }
+                // a new pulse replaces the one that's playing, so only the most recent one would be felt anyway
+                let rumble = force_feedback.amplitude();
+                if replay.is_some() {
+                    // the controller may not even be on
+                } else if !sample.session_running {
+                    // kept until the session runs again
+                } else if let Some(pulse) = pending_haptics.pop() {
+                    xr_unwrap!(
+                        tx,
+                        self.actions.haptic.apply_feedback(&self.session, xr::Path::NULL, &haptics::vibration(&pulse))
+                    );
+                } else if rumble > 0.0 {
+                    let pulse = HapticPulse { enabled: true, amplitude: rumble, duration: FF_PULSE_MS, frequency: 0.0 };
+                    xr_unwrap!(
+                        tx,
+                        self.actions.haptic.apply_feedback(&self.session, xr::Path::NULL, &haptics::vibration(&pulse))
+                    );
+                }
+                if sample.session_running {
+                    pending_haptics.clear();
+                }
+                let center = trim.apply([0.0, 0.0]);
+                let axes = failsafe.output(clutch.output(trim.apply(mapped.stick), center), center);
+
+                if clutch.buttons_frozen() || !live || !tracked {
+                    // nothing stays held on the keyboard either
+                    io_unwrap!(tx, keyboard_mouse.release());
+                } else {
+                    io_unwrap!(tx, keyboard_mouse.update(axes, &buttons, claimed, 1.0 / rate as f32));
+                }
+
+                let [x, y] = mapping::to_axes(axes);
+                previous_input = input;
+                previous_buttons = buttons;
+
+                let ev_x = InputEvent::new(3, AbsoluteAxisCode::ABS_X.0, x);
+                let ev_y = InputEvent::new(3, AbsoluteAxisCode::ABS_Y.0, y);
+
+                gamepad.live = joystick && live;
+                if joystick && live {
+                    io_unwrap!(tx, self.device.emit(&[ev_x, ev_y]));
+                    emitted.extend([ev_x, ev_y]);
+                    gamepad.axes = [ev_x.value(), ev_y.value()];
+                }
+                if send_telemetry {
+                    self.telemetry.push(VRTelemetry::Output(gamepad));
+                    last_telemetry = Instant::now();
+                }
+
+                if let Some(active) = &mut recorder {
+                    let frame = Frame {
+                        time: Duration::ZERO,
+                        orientation: sample.orientation,
+                        position: sample.position,
+                        body_yaw: sample.body_yaw,
+                        tracked,
+                        trigger: input.trigger,
+                        trackpad: input.trackpad,
+                        trackpad_click: input.trackpad_click,
+                        grip: input.grip,
+                        menu: input.menu,
+                        axes,
+                        events: emitted,
+                    };
+                    if let Err(err) = active.write(frame) {
+                        log::error!("Recording stopped: {}", err);
+                        recorder = None;
+                        let _ = tx.send(VR2UI::RecordingUpdate(None));
+                    }
+                }
+            }
+
+            next_tick += Duration::from_secs_f64(1.0 / rate as f64);
+            let now = Instant::now();
+            if next_tick > now {
+                std::thread::sleep(next_tick - now);
+            } else {
+                // running behind, don't try to catch up with a burst of samples
+                next_tick = now;
+            }
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use crate::pipe::{ReplayProgress, ReplaySettings};
+use crate::recording::Frame;
+use std::time::{Duration, Instant};
+
+/// Plays a recording back in place of the controller.
+pub struct Replay {
+    pub settings: ReplaySettings,
+    frames: Vec<Frame>,
+    position: Duration,
+    last_tick: Instant,
+}
+
+impl Replay {
+    /// `frames` must not be empty.
+    pub fn new(frames: Vec<Frame>, settings: ReplaySettings) -> Self {
+        Self { settings, frames, position: Duration::ZERO, last_tick: Instant::now() }
+    }
+
+    pub fn length(&self) -> Duration { self.frames.last().map_or(Duration::ZERO, |frame| frame.time) }
+
+    pub fn seek(&mut self, position: Duration) { self.position = position.min(self.length()); }
+
+    /// Moves playback along by the time since the last call, and returns the frame to feed through.
+    /// Playback pauses at the end instead of looping.
+    pub fn advance(&mut self) -> &Frame {
+        let elapsed = self.last_tick.elapsed();
+        self.last_tick = Instant::now();
+        if !self.settings.paused {
+            self.position = (self.position + elapsed.mul_f32(self.settings.speed.max(0.0))).min(self.length());
+        }
+
+        let index = self.frames.partition_point(|frame| frame.time <= self.position).saturating_sub(1);
+        &self.frames[index]
+    }
+
+    pub fn progress(&self) -> ReplayProgress {
+        ReplayProgress {
+            position: self.position.as_secs_f32(),
+            length: self.length().as_secs_f32(),
+            finished: self.position >= self.length(),
+        }
+    }
+}
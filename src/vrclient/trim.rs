@@ -0,0 +1,163 @@
+use crate::mapping::ControllerState;
+use crate::pipe::TrimSettings;
+
+/// Offset added to the stick output, moved around by the trim buttons.
+pub struct Trim {
+    pub settings: TrimSettings,
+    offset: [f32; 2],
+    /// Set by force trim, the output stays at the offset until the stick is back in the deadzone
+    holding: bool,
+}
+
+impl Trim {
+    pub fn new(settings: TrimSettings) -> Self { Self { settings, offset: [0.0; 2], holding: false } }
+
+    pub fn offset(&self) -> [f32; 2] { self.offset }
+
+    pub fn reset(&mut self) {
+        self.offset = [0.0; 2];
+        self.holding = false;
+    }
+
+    /// Handles trim button presses. `stick` is the current stick output before trimming, force trim
+    /// makes its trimmed value the new center.
+    ///
+    /// Returns whether the offset changed.
+    pub fn update(&mut self, input: &ControllerState, previous: &ControllerState, stick: [f32; 2]) -> bool {
+        let before = self.offset;
+        let step = self.settings.step;
+        let output = self.apply(stick);
+        // the wand has to come back to the middle before it moves the stick again
+        if stick == [0.0, 0.0] {
+            self.holding = false;
+        }
+
+        if input.just_pressed(previous, self.settings.up) {
+            self.offset[1] += step;
+        }
+        if input.just_pressed(previous, self.settings.down) {
+            self.offset[1] -= step;
+        }
+        if input.just_pressed(previous, self.settings.left) {
+            self.offset[0] -= step;
+        }
+        if input.just_pressed(previous, self.settings.right) {
+            self.offset[0] += step;
+        }
+        if input.just_pressed(previous, self.settings.force_trim) {
+            self.offset = output;
+            self.holding = stick != [0.0, 0.0];
+        }
+        if input.just_pressed(previous, self.settings.reset) {
+            self.reset();
+        }
+
+        self.offset = self.offset.map(|o| o.clamp(-1.0, 1.0));
+        self.offset != before
+    }
+
+    /// Adds the trim offset to a stick output, keeping it within `-1.0..=1.0`. Just the offset while
+    /// force trim is holding.
+    pub fn apply(&self, output: [f32; 2]) -> [f32; 2] {
+        if self.holding {
+            return self.offset;
+        }
+        [(output[0] + self.offset[0]).clamp(-1.0, 1.0), (output[1] + self.offset[1]).clamp(-1.0, 1.0)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipe::ControllerButton;
+
+    fn trim() -> Trim {
+        Trim::new(TrimSettings {
+            step: 0.1,
+            up: ControllerButton::TrackpadUp,
+            down: ControllerButton::TrackpadDown,
+            left: ControllerButton::TrackpadLeft,
+            right: ControllerButton::TrackpadRight,
+            force_trim: ControllerButton::Grip,
+            reset: ControllerButton::Menu,
+        })
+    }
+
+    fn trackpad(x: f32, y: f32) -> ControllerState {
+        ControllerState { trackpad: [x, y], trackpad_click: true, ..Default::default() }
+    }
+
+    /// Presses `input` once, starting from nothing held.
+    fn press(trim: &mut Trim, input: ControllerState, stick: [f32; 2]) -> bool {
+        let changed = trim.update(&input, &ControllerState::default(), stick);
+        trim.update(&ControllerState::default(), &input, stick);
+        changed
+    }
+
+    #[test]
+    fn nudges_move_by_a_step() {
+        let mut t = trim();
+        assert!(press(&mut t, trackpad(0.0, 1.0), [0.0, 0.0]));
+        assert!(press(&mut t, trackpad(1.0, 0.0), [0.0, 0.0]));
+        assert!(press(&mut t, trackpad(1.0, 0.0), [0.0, 0.0]));
+        assert!((t.offset()[0] - 0.2).abs() < 1e-6 && (t.offset()[1] - 0.1).abs() < 1e-6, "{:?}", t.offset());
+        assert!(press(&mut t, trackpad(-1.0, 0.0), [0.0, 0.0]));
+        assert!(press(&mut t, trackpad(0.0, -1.0), [0.0, 0.0]));
+        assert!((t.offset()[0] - 0.1).abs() < 1e-6 && t.offset()[1].abs() < 1e-6, "{:?}", t.offset());
+    }
+
+    #[test]
+    fn holding_a_nudge_moves_once() {
+        let mut t = trim();
+        let up = trackpad(0.0, 1.0);
+        assert!(t.update(&up, &ControllerState::default(), [0.0, 0.0]));
+        assert!(!t.update(&up, &up, [0.0, 0.0]));
+        assert!((t.offset()[1] - 0.1).abs() < 1e-6);
+    }
+
+    #[test]
+    fn offset_and_output_are_clamped() {
+        let mut t = trim();
+        for _ in 0..15 {
+            press(&mut t, trackpad(0.0, 1.0), [0.0, 0.0]);
+        }
+        assert_eq!(t.offset()[1], 1.0);
+        assert!(!press(&mut t, trackpad(0.0, 1.0), [0.0, 0.0]));
+        assert_eq!(t.apply([0.5, 0.5]), [0.5, 1.0]);
+    }
+
+    #[test]
+    fn force_trim_holds_until_back_in_the_deadzone() {
+        let mut t = trim();
+        press(&mut t, trackpad(1.0, 0.0), [0.0, 0.0]);
+        let grip = ControllerState { grip: true, ..Default::default() };
+
+        // the output doesn't jump when the current output becomes the center
+        let stick = [0.3, -0.2];
+        let before = t.apply(stick);
+        assert!(t.update(&grip, &ControllerState::default(), stick));
+        assert_eq!(t.offset(), before);
+        assert_eq!(t.apply(stick), before);
+
+        // on the way back to the middle
+        t.update(&ControllerState::default(), &grip, [0.1, -0.05]);
+        assert_eq!(t.apply([0.1, -0.05]), before);
+
+        // and the stick works around the new center once it's been there
+        t.update(&ControllerState::default(), &ControllerState::default(), [0.0, 0.0]);
+        assert_eq!(t.apply([0.0, 0.0]), before);
+        let moved = t.apply([0.1, 0.0]);
+        assert!((moved[0] - (before[0] + 0.1)).abs() < 1e-6, "{moved:?}");
+    }
+
+    #[test]
+    fn reset_returns_to_zero() {
+        let mut t = trim();
+        press(&mut t, trackpad(0.0, 1.0), [0.0, 0.0]);
+        t.update(&ControllerState { grip: true, ..Default::default() }, &ControllerState::default(), [0.5, 0.0]);
+        assert!(press(&mut t, ControllerState { menu: true, ..Default::default() }, [0.5, 0.0]));
+        assert_eq!(t.offset(), [0.0, 0.0]);
+        assert_eq!(t.apply([0.5, 0.0]), [0.5, 0.0]);
+        assert!(!press(&mut t, ControllerState { menu: true, ..Default::default() }, [0.0, 0.0]));
+    }
+}
@@ -0,0 +1,43 @@
+use crate::pipe::YawReference;
+use std::f32::consts::{PI, TAU};
+
+/// Tracks the heading of the frame the controller is measured in, so the stick stays aligned
+/// with the player's body when they turn their chair.
+pub struct YawFrame {
+    /// Smoothed headset heading
+    head: Option<f32>,
+    /// Headset heading at the time the seat was captured
+    seat: Option<f32>,
+}
+
+impl YawFrame {
+    pub fn new() -> Self { Self { head: None, seat: None } }
+
+    /// Captures the seat on the next update.
+    pub fn capture_seat(&mut self) { self.seat = None; }
+
+    /// Heading in radians to measure the controller against. `head_yaw` is the headset's current
+    /// heading, if it's tracked, and `dt` the time since the last update in seconds.
+    pub fn update(&mut self, reference: YawReference, smoothing: f32, head_yaw: Option<f32>, dt: f32) -> f32 {
+        if let Some(target) = head_yaw {
+            self.head = Some(match self.head {
+                Some(head) => {
+                    // take the short way around
+                    let diff = (target - head + PI).rem_euclid(TAU) - PI;
+                    let alpha = if smoothing > 0.0 { 1.0 - f32::exp(-dt / smoothing) } else { 1.0 };
+                    head + diff * alpha
+                }
+                None => target,
+            });
+            if self.seat.is_none() {
+                self.seat = head_yaw;
+            }
+        }
+
+        match reference {
+            YawReference::None => 0.0,
+            YawReference::Head => self.head.unwrap_or(0.0),
+            YawReference::Seat => self.seat.unwrap_or(0.0),
+        }
+    }
+}